@@ -0,0 +1,206 @@
+//! The commands ChiselGPT adds on top of the ones chisel provides
+
+use yansi::Paint;
+
+/// A ChiselGPT command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChiselGptCommand {
+    Chat,
+    Explain,
+    Fix,
+    ExportTest,
+    ExportScript,
+    Model,
+    Label,
+    Checkpoint,
+    Checkpoints,
+    Restore,
+    Diff,
+}
+
+/// Describes how a command is invoked and what it does, used for `!help`, completion and dispatch
+#[derive(Debug)]
+pub struct CommandDescriptor {
+    pub command: ChiselGptCommand,
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// The shape of the arguments, i.e. `[--last] [target]`
+    pub args: &'static str,
+    pub description: &'static str,
+}
+
+impl CommandDescriptor {
+    /// The name followed by the aliases
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+}
+
+/// Every ChiselGPT command, in the order they're listed in `!help`
+pub const COMMANDS: &[CommandDescriptor] = &[
+    CommandDescriptor {
+        command: ChiselGptCommand::Chat,
+        name: "chat",
+        aliases: &["chat!"],
+        args: "<request>",
+        description: "Ask the model to write and dispatch code for a request, `!chat!` skips the response cache and `!chat@<profile>` uses another model profile",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Explain,
+        name: "explain",
+        aliases: &[],
+        args: "[--last] [target]",
+        description: "Explain the session, a single definition or, with `--last`, why the last input failed",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Fix,
+        name: "fix",
+        aliases: &[],
+        args: "",
+        description: "Propose a fix for the last failed input and dispatch it once confirmed",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::ExportTest,
+        name: "export-test",
+        aliases: &["et"],
+        args: "[path]",
+        description: "Export the session as a forge test, defaults to `test/REPL.t.sol`",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::ExportScript,
+        name: "export-script",
+        aliases: &["es"],
+        args: "[path]",
+        description: "Export the session as a forge script, defaults to `script/REPL.s.sol`",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Model,
+        name: "model",
+        aliases: &[],
+        args: "[profile]",
+        description: "Switch the model profile for the session, lists the profiles without an argument",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Label,
+        name: "label",
+        aliases: &[],
+        args: "[name] [address]",
+        description: "Add an address book entry that requests can refer to by name, lists the entries without arguments",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Checkpoint,
+        name: "checkpoint",
+        aliases: &["cp"],
+        args: "<name>",
+        description: "Save the session source and state under a name to return to later",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Checkpoints,
+        name: "checkpoints",
+        aliases: &[],
+        args: "",
+        description: "List the checkpoints, including the ones created before each recipe",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Restore,
+        name: "restore",
+        aliases: &[],
+        args: "<name>",
+        description: "Go back to a checkpoint, the session is re-executed from its source",
+    },
+    CommandDescriptor {
+        command: ChiselGptCommand::Diff,
+        name: "diff",
+        aliases: &[],
+        args: "[n]",
+        description: "Show how the n-th most recent recipe changed the session source, defaults to the last one",
+    },
+];
+
+impl ChiselGptCommand {
+    /// Parses the command at the start of the line, returns it along with the rest of the line
+    pub fn parse(line: &str) -> Option<(Self, &str)> {
+        let line = line.trim_start().strip_prefix('!')?;
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        // Chat requests can pick a profile, i.e. `!chat@strong`
+        let (name, profile) = match name.split_once('@') {
+            Some((name, profile)) => (name, Some(profile)),
+            None => (name, None),
+        };
+
+        let command = COMMANDS
+            .iter()
+            .find(|descriptor| descriptor.names().any(|n| n == name))?
+            .command;
+
+        match (command, profile) {
+            (Self::Chat, _) | (_, None) => Some((command, args.trim())),
+            _ => None,
+        }
+    }
+}
+
+/// The ChiselGPT section appended to chisel's `!help` output, in the same layout
+pub fn help_section() -> String {
+    let commands = COMMANDS
+        .iter()
+        .map(|descriptor| {
+            let names = descriptor
+                .names()
+                .map(|name| {
+                    let usage = format!("{name} {}", descriptor.args);
+                    format!("!{}", Paint::green(usage.trim_end()))
+                })
+                .collect::<Vec<_>>()
+                .join(" | ");
+
+            format!("\t{names} - {}", descriptor.description)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("{}\n{commands}\n", Paint::magenta("ChiselGPT").bold())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::command_helper::strip_ansi;
+
+    #[test]
+    fn it_parses_commands_and_aliases() {
+        assert_eq!(
+            ChiselGptCommand::parse("!chat wrap 1 ether"),
+            Some((ChiselGptCommand::Chat, "wrap 1 ether"))
+        );
+        assert_eq!(
+            ChiselGptCommand::parse("!chat! wrap 1 ether"),
+            Some((ChiselGptCommand::Chat, "wrap 1 ether"))
+        );
+        assert_eq!(
+            ChiselGptCommand::parse("!es script/Wrap.s.sol"),
+            Some((ChiselGptCommand::ExportScript, "script/Wrap.s.sol"))
+        );
+        assert_eq!(
+            ChiselGptCommand::parse("!chat@strong write an erc20"),
+            Some((ChiselGptCommand::Chat, "write an erc20"))
+        );
+        assert_eq!(
+            ChiselGptCommand::parse("!fix"),
+            Some((ChiselGptCommand::Fix, ""))
+        );
+        assert_eq!(ChiselGptCommand::parse("!fix@strong"), None);
+        assert_eq!(ChiselGptCommand::parse("!fetch 0x0 WETH"), None);
+        assert_eq!(ChiselGptCommand::parse("!chatty"), None);
+    }
+
+    #[test]
+    fn it_lists_every_command_in_help() {
+        let help = strip_ansi(&help_section());
+
+        assert!(help.contains("!chat <request> | !chat! <request> - "));
+        assert!(help.contains("!fix - "));
+        assert_eq!(help.lines().count(), COMMANDS.len() + 1);
+    }
+}
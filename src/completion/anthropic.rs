@@ -0,0 +1,580 @@
+use std::{env, error::Error};
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{profile::ModelProfile, transcript::TranscriptEntry};
+
+/// Where requests are sent unless `ANTHROPIC_BASE_URL` says otherwise
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+
+/// The version of the Messages API the requests and events below follow
+const API_VERSION: &str = "2023-06-01";
+
+/// Name of the tool the model can hand a recipe to, instead of writing it out between tags
+const RECIPE_TOOL_NAME: &str = "cook_recipe";
+
+type AnthropicResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Debug, Serialize)]
+struct Message<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u16,
+    temperature: f32,
+    /// Unlike OpenAI, the system prompt is a top level field rather than the first message
+    system: &'a str,
+    messages: Vec<Message<'a>>,
+    tools: Value,
+    stream: bool,
+}
+
+/// Why the model stopped generating
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    EndTurn,
+    /// The response was cut off by the `max_tokens` of the profile
+    MaxTokens,
+    StopSequence,
+    ToolUse,
+    #[serde(other)]
+    Other,
+}
+
+/// A block of the response content
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentBlock {
+    Text(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+}
+
+/// A complete response, assembled from the streamed events
+#[derive(Debug, Default)]
+pub struct MessagesResponse {
+    pub content: Vec<ContentBlock>,
+    pub stop_reason: Option<StopReason>,
+}
+
+impl MessagesResponse {
+    /// The response as text. Recipes handed to the recipe tool are written out between the recipe
+    /// tags, so that they're split into ingredients like any other response.
+    pub fn into_text(self) -> String {
+        let mut text = String::new();
+
+        for block in self.content {
+            match block {
+                ContentBlock::Text(block) => text.push_str(&block),
+                ContentBlock::ToolUse { name, input, .. } if name == RECIPE_TOOL_NAME => {
+                    let ingredients: Vec<&str> = input["ingredients"]
+                        .as_array()
+                        .map(|ingredients| ingredients.iter().filter_map(Value::as_str).collect())
+                        .unwrap_or_default();
+
+                    text.push_str("\n##START##\n");
+                    text.push_str(&ingredients.join("\n"));
+                    text.push_str("\n##END##\n");
+                }
+                // Only the recipe tool is offered
+                ContentBlock::ToolUse { .. } => {}
+            }
+        }
+
+        text
+    }
+}
+
+/// The events of a streamed response, see <https://docs.anthropic.com/en/api/messages-streaming>
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    ContentBlockStart {
+        index: usize,
+        content_block: BlockStart,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+    },
+    Error {
+        error: ApiError,
+    },
+    /// `message_start`, `content_block_stop`, `message_stop`, `ping` and anything added later
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockStart {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDelta {
+    stop_reason: Option<StopReason>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+/// The body of a failed request
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+/// A content block that is still being streamed
+#[derive(Debug)]
+enum PartialBlock {
+    Text(String),
+    /// The input of a tool call arrives as pieces of JSON that only parse once complete
+    ToolUse {
+        id: String,
+        name: String,
+        json: String,
+    },
+    Ignored,
+}
+
+/// Splits a stream of server-sent events into the `data` of each event. Chunks can end anywhere,
+/// including in the middle of a character, so only complete events are decoded.
+#[derive(Debug, Default)]
+struct EventStream {
+    buffer: Vec<u8>,
+}
+
+impl EventStream {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some((end, separator_len)) = Self::event_end(&self.buffer) {
+            let event: Vec<u8> = self.buffer.drain(..end + separator_len).take(end).collect();
+
+            let data: Vec<&str> = std::str::from_utf8(&event)
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+
+        events
+    }
+
+    /// The end of the first complete event and the length of the blank line after it
+    fn event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+        let lf = buffer.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2));
+        let crlf = buffer
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|i| (i, 4));
+
+        match (lf, crlf) {
+            (Some(lf), Some(crlf)) => Some(lf.min(crlf)),
+            (lf, crlf) => lf.or(crlf),
+        }
+    }
+}
+
+/// Client for the Anthropic Messages API
+#[derive(Debug, Clone)]
+pub struct AnthropicClient {
+    http: Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl AnthropicClient {
+    /// Creates a client configured through `ANTHROPIC_API_KEY` and `ANTHROPIC_BASE_URL`
+    pub fn new() -> Self {
+        Self::with_base_url(
+            env::var("ANTHROPIC_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string()),
+            env::var("ANTHROPIC_API_KEY").ok(),
+        )
+    }
+
+    pub fn with_base_url(base_url: String, api_key: Option<String>) -> Self {
+        Self {
+            http: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    /// Sends a request and streams the response
+    pub async fn complete(
+        &self,
+        profile: &ModelProfile,
+        context: &str,
+        history: &[TranscriptEntry],
+        request: &str,
+    ) -> AnthropicResult<MessagesResponse> {
+        let api_key = self
+            .api_key
+            .as_deref()
+            .ok_or("ANTHROPIC_API_KEY is not set")?;
+
+        let mut messages = Vec::with_capacity(history.len() * 2 + 1);
+        for entry in history {
+            messages.push(Message {
+                role: "user",
+                content: &entry.request,
+            });
+            messages.push(Message {
+                role: "assistant",
+                content: &entry.raw_response,
+            });
+        }
+        messages.push(Message {
+            role: "user",
+            content: request,
+        });
+
+        let body = MessagesRequest {
+            model: &profile.model,
+            max_tokens: profile.max_tokens,
+            temperature: profile.temperature,
+            system: context,
+            messages,
+            tools: recipe_tool(),
+            stream: true,
+        };
+
+        let mut response = self
+            .http
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(ErrorResponse { error }) => {
+                    format!(
+                        "Anthropic API error ({status}, {}): {}",
+                        error.kind, error.message
+                    )
+                }
+                Err(_) => format!("Anthropic API error ({status}): {body}"),
+            }
+            .into());
+        }
+
+        let mut stream = EventStream::default();
+        let mut blocks: Vec<PartialBlock> = Vec::new();
+        let mut stop_reason = None;
+
+        while let Some(chunk) = response.chunk().await? {
+            for data in stream.push(&chunk) {
+                match serde_json::from_str::<StreamEvent>(&data)? {
+                    StreamEvent::ContentBlockStart {
+                        index,
+                        content_block,
+                    } => {
+                        let block = match content_block {
+                            BlockStart::Text { text } => PartialBlock::Text(text),
+                            BlockStart::ToolUse { id, name } => PartialBlock::ToolUse {
+                                id,
+                                name,
+                                json: String::new(),
+                            },
+                            BlockStart::Other => PartialBlock::Ignored,
+                        };
+
+                        if index >= blocks.len() {
+                            blocks.resize_with(index + 1, || PartialBlock::Ignored);
+                        }
+                        blocks[index] = block;
+                    }
+                    StreamEvent::ContentBlockDelta { index, delta } => {
+                        match (blocks.get_mut(index), delta) {
+                            (
+                                Some(PartialBlock::Text(text)),
+                                BlockDelta::TextDelta { text: delta },
+                            ) => text.push_str(&delta),
+                            (
+                                Some(PartialBlock::ToolUse { json, .. }),
+                                BlockDelta::InputJsonDelta { partial_json },
+                            ) => json.push_str(&partial_json),
+                            _ => {}
+                        }
+                    }
+                    StreamEvent::MessageDelta { delta } => {
+                        if delta.stop_reason.is_some() {
+                            stop_reason = delta.stop_reason;
+                        }
+                    }
+                    // Errors can also arrive after the stream started, i.e. when overloaded
+                    StreamEvent::Error { error } => {
+                        return Err(format!(
+                            "Anthropic API error ({}): {}",
+                            error.kind, error.message
+                        )
+                        .into())
+                    }
+                    StreamEvent::Other => {}
+                }
+            }
+        }
+
+        let mut content = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            match block {
+                PartialBlock::Text(text) => content.push(ContentBlock::Text(text)),
+                PartialBlock::ToolUse { id, name, json } => {
+                    // Tools without parameters stream no input at all
+                    let input = if json.trim().is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(&json)?
+                    };
+                    content.push(ContentBlock::ToolUse { id, name, input });
+                }
+                PartialBlock::Ignored => {}
+            }
+        }
+
+        Ok(MessagesResponse {
+            content,
+            stop_reason,
+        })
+    }
+}
+
+/// The tool the model can use to hand over a recipe. Calling it ends the turn, the ingredients
+/// are dispatched rather than sent back as a tool result.
+fn recipe_tool() -> Value {
+    json!([{
+        "name": RECIPE_TOOL_NAME,
+        "description": "Dispatch a recipe into the Chisel session. Each ingredient is a Chisel command or a piece of Solidity code, they are dispatched in order.",
+        "input_schema": {
+            "type": "object",
+            "properties": {
+                "ingredients": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "The Chisel commands and Solidity code to dispatch, in order"
+                }
+            },
+            "required": ["ingredients"]
+        }
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::completion::mock_server::{mock_server, request_body, MockResponse};
+
+    /// A streamed response made up of the events
+    fn stream(events: &[Value]) -> MockResponse {
+        MockResponse {
+            status: "200 OK",
+            content_type: "text/event-stream",
+            body: events
+                .iter()
+                .map(|event| {
+                    format!(
+                        "event: {}\ndata: {event}\n\n",
+                        event["type"].as_str().unwrap()
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn history() -> Vec<TranscriptEntry> {
+        vec![TranscriptEntry {
+            request: "!chat deploy a counter".to_string(),
+            raw_response: "##START##\nCounter counter = new Counter();\n##END##".to_string(),
+            ingredients: vec![],
+        }]
+    }
+
+    #[tokio::test]
+    async fn it_streams_text_responses() {
+        let response = stream(&[
+            json!({"type": "message_start", "message": {"id": "msg_1", "content": [], "stop_reason": null}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "ping"}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "##START##\ncounter"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": ".increment();\n##END##"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn", "stop_sequence": null}, "usage": {"output_tokens": 12}}),
+            json!({"type": "message_stop"}),
+        ]);
+        let (base_url, server) = mock_server(vec![response]).await;
+
+        let client = AnthropicClient::with_base_url(base_url, Some("test-key".to_string()));
+        let response = client
+            .complete(
+                &ModelProfile::default(),
+                "You are ChiselGPT",
+                &history(),
+                "!chat increment it",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+        assert_eq!(
+            response.into_text(),
+            "##START##\ncounter.increment();\n##END##"
+        );
+
+        let request = &server.await.unwrap()[0];
+        let request_lowercase = request.to_lowercase();
+        assert!(request.starts_with("POST /v1/messages "));
+        assert!(request_lowercase.contains("x-api-key: test-key"));
+        assert!(request_lowercase.contains(&format!("anthropic-version: {API_VERSION}")));
+
+        let body = request_body(request);
+        assert_eq!(body["system"], "You are ChiselGPT");
+        assert_eq!(body["stream"], true);
+        assert_eq!(
+            body["messages"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|message| message["role"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["user", "assistant", "user"]
+        );
+        assert_eq!(body["tools"][0]["name"], RECIPE_TOOL_NAME);
+    }
+
+    #[tokio::test]
+    async fn it_turns_tool_use_into_a_recipe() {
+        let response = stream(&[
+            json!({"type": "message_start", "message": {"id": "msg_2", "content": []}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": "Cooking"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": RECIPE_TOOL_NAME, "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"ingredients\": [\"uint256 a = 1;\", "}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"a += 1;\"]}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}}),
+            json!({"type": "message_stop"}),
+        ]);
+        let (base_url, server) = mock_server(vec![response]).await;
+
+        let client = AnthropicClient::with_base_url(base_url, Some("test-key".to_string()));
+        let response = client
+            .complete(&ModelProfile::default(), "", &[], "!chat count to two")
+            .await
+            .unwrap();
+        server.await.unwrap();
+
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(
+            response.content[1],
+            ContentBlock::ToolUse {
+                id: "toolu_1".to_string(),
+                name: RECIPE_TOOL_NAME.to_string(),
+                input: json!({"ingredients": ["uint256 a = 1;", "a += 1;"]}),
+            }
+        );
+        assert_eq!(
+            response.into_text(),
+            "Cooking\n##START##\nuint256 a = 1;\na += 1;\n##END##\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reports_api_errors() {
+        let body = json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}});
+        let (base_url, server) =
+            mock_server(vec![MockResponse::json("529 Site Overloaded", body)]).await;
+
+        let client = AnthropicClient::with_base_url(base_url, Some("test-key".to_string()));
+        let error = client
+            .complete(&ModelProfile::default(), "", &[], "!chat hi")
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+
+        assert!(error.to_string().contains("overloaded_error"));
+        assert!(error.to_string().contains("Overloaded"));
+    }
+
+    #[tokio::test]
+    async fn it_reports_errors_mid_stream() {
+        let response = stream(&[
+            json!({"type": "message_start", "message": {"id": "msg_3", "content": []}}),
+            json!({"type": "error", "error": {"type": "api_error", "message": "Internal server error"}}),
+        ]);
+        let (base_url, server) = mock_server(vec![response]).await;
+
+        let client = AnthropicClient::with_base_url(base_url, Some("test-key".to_string()));
+        let error = client
+            .complete(&ModelProfile::default(), "", &[], "!chat hi")
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+
+        assert!(error.to_string().contains("Internal server error"));
+    }
+
+    #[test]
+    fn it_splits_events_across_chunks() {
+        let mut stream = EventStream::default();
+        let event = "event: content_block_delta\r\ndata: {\"text\": \"ü\"}\r\n\r\nevent: ping\ndata: {}\n\n";
+        let bytes = event.as_bytes();
+
+        // Split in the middle of the `ü`
+        let split = event.find('ü').unwrap() + 1;
+        assert!(stream.push(&bytes[..split]).is_empty());
+        assert_eq!(
+            stream.push(&bytes[split..]),
+            vec!["{\"text\": \"ü\"}".to_string(), "{}".to_string()]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::helpers::data_dir::cache_dir;
+
+/// Name of the directory, inside of the ChiselGPT cache directory, that holds cached responses
+const RESPONSES_DIR_NAME: &str = "responses";
+
+/// Cached responses older than this are ignored and replaced on the next request
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
+/// Upper bound on the number of cached responses kept on disk
+const DEFAULT_MAX_ENTRIES: usize = 256;
+
+/// Upper bound on the total size of the cached responses kept on disk
+const DEFAULT_MAX_BYTES: u64 = 8 * 1024 * 1024;
+
+/// A single cached model response
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    /// Unix timestamp of when the response was stored
+    created_at: u64,
+    /// The model that produced the response
+    model: String,
+    /// The raw response content
+    response: String,
+}
+
+/// On-disk cache of model responses, keyed on the model, the rendered prompt and the request.
+///
+/// Entries live in the ChiselGPT cache directory, `!clearcache` and `chisel_gpt clear-cache` remove
+/// them along with chisel's cached sessions.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_entries: usize,
+    max_bytes: u64,
+}
+
+impl ResponseCache {
+    /// Creates a cache in the default location, returns `None` if the cache directory can't be
+    /// resolved or created.
+    pub fn new() -> Option<Self> {
+        let dir = Self::dir()?;
+        fs::create_dir_all(&dir).ok()?;
+
+        Some(Self {
+            dir,
+            ttl: DEFAULT_TTL,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: DEFAULT_MAX_BYTES,
+        })
+    }
+
+    /// The directory cached responses are written to
+    pub fn dir() -> Option<PathBuf> {
+        cache_dir().map(|dir| dir.join(RESPONSES_DIR_NAME))
+    }
+
+    /// Removes every cached response
+    pub fn clear() -> std::io::Result<()> {
+        match Self::dir() {
+            Some(dir) if dir.exists() => fs::remove_dir_all(dir),
+            _ => Ok(()),
+        }
+    }
+
+    /// Computes the cache key for a request, the prompt must be the fully rendered system context
+    pub fn key(model: &str, prompt: &str, request: &str) -> String {
+        let mut hasher = Sha256::new();
+
+        // Length prefix each part so that moving text between them changes the key
+        for part in [model, prompt, request] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part.as_bytes());
+        }
+
+        hex::encode(hasher.finalize())
+    }
+
+    /// Returns the cached response for `key`, if there is one that hasn't expired
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.entry_path(key);
+        let contents = fs::read_to_string(&path).ok()?;
+        let entry: CachedResponse = serde_json::from_str(&contents).ok()?;
+
+        if now().saturating_sub(entry.created_at) > self.ttl.as_secs() {
+            let _ = fs::remove_file(path);
+            return None;
+        }
+
+        Some(entry.response)
+    }
+
+    /// Stores a response and prunes the cache back within its size limits
+    pub fn put(&self, key: &str, model: &str, response: &str) -> std::io::Result<()> {
+        let entry = CachedResponse {
+            created_at: now(),
+            model: model.to_string(),
+            response: response.to_string(),
+        };
+
+        fs::write(self.entry_path(key), serde_json::to_string(&entry)?)?;
+
+        self.prune()
+    }
+
+    /// Removes expired entries, then the oldest entries until both the entry count and the total
+    /// size are within the limits. Files other than the entries written by [Self::put] are left
+    /// alone.
+    fn prune(&self) -> std::io::Result<()> {
+        let mut entries = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() || !is_entry_path(&entry.path()) {
+                continue;
+            }
+
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            let expired = SystemTime::now()
+                .duration_since(modified)
+                .map(|age| age > self.ttl)
+                .unwrap_or(false);
+
+            if expired {
+                fs::remove_file(entry.path())?;
+            } else {
+                entries.push((modified, metadata.len(), entry.path()));
+            }
+        }
+
+        // Newest first, so everything past the limits is the oldest
+        entries.sort_by_key(|entry| Reverse(entry.0));
+
+        let mut total_bytes = 0;
+        for (index, (_, len, path)) in entries.into_iter().enumerate() {
+            total_bytes += len;
+            if index >= self.max_entries || total_bytes > self.max_bytes {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+/// Whether `path` is named like a cache entry, `<sha256 of the key>.json`
+fn is_entry_path(path: &Path) -> bool {
+    let is_json = path
+        .extension()
+        .is_some_and(|extension| extension == "json");
+    let is_key = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.len() == 64 && stem.bytes().all(|byte| byte.is_ascii_hexdigit()));
+
+    is_json && is_key
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+
+    /// A cache in an empty directory of its own
+    fn cache(name: &str) -> ResponseCache {
+        let dir =
+            std::env::temp_dir().join(format!("chisel_gpt_cache_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        ResponseCache {
+            dir,
+            ttl: DEFAULT_TTL,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    fn key(request: &str) -> String {
+        ResponseCache::key("gpt-4", "prompt", request)
+    }
+
+    #[test]
+    fn it_derives_stable_keys() {
+        // Keys are file names on disk, changing how they're derived orphans every cached response
+        assert_eq!(
+            key("deal me 1 ether"),
+            "2a028da58a2ae4d381c378406a1ae5379c59565aa3feadef0183682fa772185c"
+        );
+
+        assert_ne!(key("deal me 1 ether"), key("deal me 2 ether"));
+        assert_ne!(
+            key("deal me 1 ether"),
+            ResponseCache::key("gpt-3.5-turbo", "prompt", "deal me 1 ether")
+        );
+        assert_ne!(
+            ResponseCache::key("gpt-4", "prompt\n", "request"),
+            ResponseCache::key("gpt-4", "prompt", "\nrequest")
+        );
+    }
+
+    #[test]
+    fn it_ignores_expired_responses() {
+        let cache = cache("expired");
+        let key = key("deal me 1 ether");
+
+        cache
+            .put(&key, "gpt-4", "vm.deal(address(this), 1 ether);")
+            .unwrap();
+        assert_eq!(
+            cache.get(&key).as_deref(),
+            Some("vm.deal(address(this), 1 ether);")
+        );
+
+        let expired = CachedResponse {
+            created_at: now() - DEFAULT_TTL.as_secs() - 1,
+            model: "gpt-4".to_string(),
+            response: "vm.deal(address(this), 1 ether);".to_string(),
+        };
+        fs::write(
+            cache.entry_path(&key),
+            serde_json::to_string(&expired).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(cache.get(&key), None);
+        assert!(!cache.entry_path(&key).exists());
+    }
+
+    #[test]
+    fn it_prunes_expired_entries_but_nothing_else() {
+        let mut cache = cache("prune_expired");
+        cache.put(&key("old"), "gpt-4", "old").unwrap();
+
+        let other_file = cache.dir.join("policy.log");
+        let other_dir = cache.dir.join("transcripts");
+        fs::write(&other_file, "{}").unwrap();
+        fs::create_dir_all(&other_dir).unwrap();
+
+        sleep(Duration::from_millis(20));
+        cache.ttl = Duration::from_millis(10);
+        cache.put(&key("new"), "gpt-4", "new").unwrap();
+
+        assert!(!cache.entry_path(&key("old")).exists());
+        assert!(cache.entry_path(&key("new")).exists());
+        assert!(other_file.exists());
+        assert!(other_dir.exists());
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_entries_past_the_entry_limit() {
+        let mut cache = cache("evict_entries");
+        cache.max_entries = 2;
+        fs::write(cache.dir.join("address_book.json"), "{}").unwrap();
+
+        for request in ["first", "second", "third"] {
+            cache.put(&key(request), "gpt-4", request).unwrap();
+            sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(cache.get(&key("first")), None);
+        assert_eq!(cache.get(&key("second")).as_deref(), Some("second"));
+        assert_eq!(cache.get(&key("third")).as_deref(), Some("third"));
+        assert!(cache.dir.join("address_book.json").exists());
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_entries_past_the_size_limit() {
+        let mut cache = cache("evict_bytes");
+        cache.put(&key("first"), "gpt-4", &"a".repeat(100)).unwrap();
+        sleep(Duration::from_millis(20));
+        let entry_len = fs::metadata(cache.entry_path(&key("first"))).unwrap().len();
+
+        cache.max_bytes = entry_len * 3 / 2;
+        cache
+            .put(&key("second"), "gpt-4", &"b".repeat(100))
+            .unwrap();
+
+        assert_eq!(cache.get(&key("first")), None);
+        assert!(cache.get(&key("second")).is_some());
+    }
+}
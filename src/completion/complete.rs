@@ -1,9 +1,15 @@
 use chisel::{
-    prelude::{format_source, ChiselCommand, ChiselDispatcher, DispatchResult},
+    prelude::{
+        format_source, ChiselCommand, ChiselDispatcher, DispatchResult, SessionSource,
+        COMMAND_LEADER,
+    },
     solidity_helper::SolidityHelper,
 };
 use foundry_config::FormatterConfig;
-use std::error::Error;
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+};
 use yansi::Paint;
 
 use async_openai::{
@@ -14,44 +20,142 @@ use async_openai::{
     Client,
 };
 
-use crate::helpers::{dispatch::log_dispatch_result, split_commands::split_commands};
+use crate::helpers::{
+    address_book::AddressBook,
+    checkpoints::Checkpoints,
+    compile_check::{check_recipe, RecipeCheck},
+    diff::SourceChange,
+    dispatch::log_dispatch_result,
+    external_contracts::ExternalContracts,
+    fork::{self, ActiveFork, ForkInfo},
+    policy::Policy,
+    recipe::render_ingredient,
+    rpc::RpcAliases,
+    session::{formatted_session_source, run_code_len, session_source},
+    source_index::find_definition,
+    split_commands::split_commands,
+};
+
+use super::{
+    anthropic::{AnthropicClient, StopReason},
+    cache::ResponseCache,
+    context::{
+        address_book_context, create_compact_context_string, create_context_string,
+        create_explain_context_string, create_fix_context_string, estimate_tokens,
+        executions_context, external_contracts_context, fork_context, render_prompt_template,
+    },
+    executions::{self, Execution, Executions},
+    ollama::OllamaClient,
+    profile::{Backend, ChiselGptConfig, ModelProfile},
+    transcript::{IngredientOutcome, IngredientRecord, Transcript, TranscriptEntry},
+};
+
+/// The prefix of a chat request that bypasses the response cache
+const FORCE_REFRESH_PREFIX: &str = "!chat!";
+
+/// The prefix of a chat request that is sent with another profile than the active one, i.e.
+/// `!chat@strong`
+const PROFILE_PREFIX: &str = "!chat@";
+
+/// The number of previous requests and responses sent along with each request
+const HISTORY_LENGTH: usize = 5;
 
-use super::context::create_context_string;
+/// The number of recipes whose changes to the session source are kept for `!diff`
+const MAX_SOURCE_CHANGES: usize = 20;
 
 fn build_request(
+    profile: &ModelProfile,
     request: String,
-    help_text: String,
-    chisel_context: String,
+    context: String,
+    history: &[TranscriptEntry],
 ) -> Result<CreateChatCompletionRequest, Box<dyn Error>> {
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(512u16)
-        .model("gpt-3.5-turbo")
-        .temperature(0.0) // To get close-to deterministic results
-        .messages([
+    let mut messages = vec![ChatCompletionRequestMessageArgs::default()
+        .role(Role::System)
+        .content(context)
+        .build()?];
+
+    for entry in history {
+        messages.push(
             ChatCompletionRequestMessageArgs::default()
-                .role(Role::System)
-                .content(create_context_string(help_text, chisel_context))
+                .role(Role::User)
+                .content(&entry.request)
                 .build()?,
+        );
+        messages.push(
             ChatCompletionRequestMessageArgs::default()
-                .role(Role::User)
-                .content(request)
+                .role(Role::Assistant)
+                .content(&entry.raw_response)
                 .build()?,
-        ])
+        );
+    }
+
+    messages.push(
+        ChatCompletionRequestMessageArgs::default()
+            .role(Role::User)
+            .content(request)
+            .build()?,
+    );
+
+    let request = CreateChatCompletionRequestArgs::default()
+        .max_tokens(profile.max_tokens)
+        .model(&profile.model)
+        .temperature(profile.temperature)
+        .messages(messages)
         .build()?;
 
     Ok(request)
 }
 
+fn warn_max_tokens(profile: &ModelProfile) {
+    eprintln!(
+        "{}",
+        Paint::yellow(format!(
+            "The response hit the profile's limit of {} tokens and may be cut off",
+            profile.max_tokens
+        ))
+    );
+}
+
 pub struct CompletionClient {
     client: Client,
+    anthropic: AnthropicClient,
+    ollama: OllamaClient,
     help_text: String,
     formatter_config: FormatterConfig,
+    /// Cache of previous responses, `None` when caching is disabled
+    cache: Option<ResponseCache>,
+    /// The chat history of the current session
+    transcript: Transcript,
+    config: ChiselGptConfig,
+    /// Name of the active profile
+    profile: String,
+    /// The `rpc_endpoints` forks in generated code are pointed at
+    rpc_aliases: RpcAliases,
+    /// Addresses requests can refer to by name
+    address_book: AddressBook,
+    /// What is known about each fork, so that each node is only asked once
+    forks: HashMap<ActiveFork, ForkInfo>,
+    /// Contracts fetched with `!fetch` during the session
+    external_contracts: ExternalContracts,
+    /// What came of the inputs dispatched most recently
+    executions: Executions,
+    /// Restore points of the session, one is created before each recipe
+    checkpoints: Checkpoints,
+    /// How the most recent recipes changed the session source, oldest first
+    source_changes: VecDeque<SourceChange>,
 }
 
 type OpenAIResult<T> = Result<T, Box<dyn Error>>;
 
 impl CompletionClient {
-    pub async fn new(dispatcher: &mut ChiselDispatcher, formatter_config: FormatterConfig) -> Self {
+    pub async fn new(
+        dispatcher: &mut ChiselDispatcher,
+        formatter_config: FormatterConfig,
+        use_cache: bool,
+        config: ChiselGptConfig,
+        rpc_aliases: RpcAliases,
+        address_book: AddressBook,
+    ) -> Self {
         let client = Client::new();
 
         let help_result = dispatcher.dispatch_command(ChiselCommand::Help, &[]).await;
@@ -63,29 +167,384 @@ impl CompletionClient {
 
         Self {
             client,
+            anthropic: AnthropicClient::new(),
+            ollama: OllamaClient::new(),
             help_text: help_text.unwrap(),
             formatter_config,
+            cache: use_cache.then(ResponseCache::new).flatten(),
+            transcript: Transcript::default(),
+            profile: config.default_profile().to_string(),
+            config,
+            rpc_aliases,
+            address_book,
+            forks: HashMap::new(),
+            external_contracts: ExternalContracts::default(),
+            executions: Executions::default(),
+            checkpoints: Checkpoints::default(),
+            source_changes: VecDeque::new(),
+        }
+    }
+
+    /// Name of the active profile
+    pub fn profile_name(&self) -> &str {
+        &self.profile
+    }
+
+    /// The configured profiles, by name
+    pub fn profiles(&self) -> impl Iterator<Item = (&String, &ModelProfile)> {
+        self.config.profiles.iter()
+    }
+
+    /// Switches the profile used for the rest of the session
+    pub fn set_profile(&mut self, name: &str) -> OpenAIResult<&ModelProfile> {
+        self.profile_by_name(name)?;
+        self.profile = name.to_string();
+        Ok(self.active_profile())
+    }
+
+    fn profile_by_name(&self, name: &str) -> OpenAIResult<&ModelProfile> {
+        self.config.profile(name).ok_or_else(|| {
+            let names: Vec<&str> = self.config.profiles.keys().map(String::as_str).collect();
+            format!(
+                "No profile named `{name}`, the configured profiles are: {}",
+                names.join(", ")
+            )
+            .into()
+        })
+    }
+
+    fn active_profile(&self) -> &ModelProfile {
+        // The active profile is always one of the configured ones
+        self.config.profile(&self.profile).unwrap()
+    }
+
+    /// Restores the chat history of a cached session, so that conversational memory resumes
+    pub fn load_transcript(&mut self, id: &str) {
+        self.transcript = Transcript::load(id).unwrap_or_default();
+    }
+
+    /// Persists the chat history next to the cached session, if the session has been saved
+    pub fn save_transcript(&self, dispatcher: &ChiselDispatcher) {
+        let Some(id) = dispatcher.session.id.as_ref() else {
+            return;
+        };
+
+        if let Err(e) = self.transcript.save(id) {
+            eprintln!(
+                "{}",
+                Paint::red(format!("Failed to save chat transcript: {e}"))
+            );
+        }
+    }
+
+    /// Forgets the chat history, used when the session itself is cleared
+    pub fn clear_transcript(&mut self) {
+        self.transcript = Transcript::default();
+    }
+
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    /// The rules model generated ingredients are checked against
+    pub fn policy(&self) -> &Policy {
+        &self.config.policy
+    }
+
+    pub fn external_contracts_mut(&mut self) -> &mut ExternalContracts {
+        &mut self.external_contracts
+    }
+
+    /// Remembers what came of dispatching `input`, `before` is the session source from before it
+    /// was dispatched and is used to trace the input. The output of chisel commands, i.e.
+    /// `!source`, is either part of the prompt already or of no use to the model, so they are left
+    /// out.
+    pub async fn record_execution(
+        &mut self,
+        before: Option<&SessionSource>,
+        input: &str,
+        result: &DispatchResult,
+    ) {
+        if input.trim().starts_with(COMMAND_LEADER) {
+            return;
         }
+
+        let trace = match before {
+            Some(source) => executions::trace(source, input).await,
+            None => None,
+        };
+
+        self.executions.push(Execution {
+            input: input.to_string(),
+            outcome: result.into(),
+            trace,
+        });
+    }
+
+    /// Forgets what the session did, used when it is cleared or another one is loaded
+    pub fn forget_session(&mut self) {
+        self.external_contracts.clear();
+        self.executions.clear();
+        self.checkpoints = Checkpoints::default();
+        self.source_changes.clear();
+    }
+
+    /// How the `n`-th most recent recipe changed the session source, `1` being the last one
+    pub fn source_change(&self, n: usize) -> Option<&SourceChange> {
+        self.source_changes.iter().rev().nth(n.checked_sub(1)?)
+    }
+
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Captures the session under `name`, along with how far the chat history had got
+    pub async fn create_checkpoint(
+        &mut self,
+        dispatcher: &ChiselDispatcher,
+        name: &str,
+    ) -> eyre::Result<()> {
+        let fork_block_number = self.session_fork_block(dispatcher).await;
+        self.checkpoints.create(
+            dispatcher,
+            name,
+            self.transcript.entries.len(),
+            fork_block_number,
+        )?;
+        Ok(())
+    }
+
+    /// Restores the session and chat history of a checkpoint, the results of the inputs and the
+    /// source changes since no longer apply
+    pub fn restore_checkpoint(
+        &mut self,
+        dispatcher: &mut ChiselDispatcher,
+        name: &str,
+    ) -> eyre::Result<()> {
+        let checkpoint = self.checkpoints.restore(dispatcher, name)?;
+        self.transcript.entries.truncate(checkpoint.transcript_len);
+        self.executions.clear();
+        // Diffs of recipes that were undone would show changes the session no longer has
+        self.source_changes.clear();
+        Ok(())
+    }
+
+    pub fn address_book(&self) -> &AddressBook {
+        &self.address_book
+    }
+
+    pub fn address_book_mut(&mut self) -> &mut AddressBook {
+        &mut self.address_book
+    }
+
+    /// The chain the session is forked from, `None` if it isn't forked
+    pub async fn fork_info(&mut self, dispatcher: &ChiselDispatcher) -> Option<ForkInfo> {
+        let fork = fork::active_fork(dispatcher, &self.rpc_aliases)?;
+        Some(self.fork_info_of(fork).await)
+    }
+
+    /// The block the fork the session was started on, or switched to with `!fork`, is at.
+    /// Checkpoints pin it, since running the source again would fork the latest block.
+    async fn session_fork_block(&mut self, dispatcher: &ChiselDispatcher) -> Option<u64> {
+        let evm_opts = &dispatcher.session.session_source.as_ref()?.config.evm_opts;
+        let fork = ActiveFork {
+            url: evm_opts.fork_url.clone()?,
+            block_number: evm_opts.fork_block_number,
+        };
+        self.fork_info_of(fork).await.block_number
+    }
+
+    /// What is known about `fork`, the node is only asked the first time
+    async fn fork_info_of(&mut self, fork: ActiveFork) -> ForkInfo {
+        if let Some(info) = self.forks.get(&fork) {
+            return info.clone();
+        }
+
+        let mut info = ForkInfo::fetch(&fork.url, fork.block_number).await;
+        info.alias = self.rpc_aliases.alias_of(&fork.url).map(str::to_string);
+        // A node that can't be reached right now may well be reachable for the next request
+        if info.chain_id.is_some() {
+            self.forks.insert(fork, info.clone());
+        }
+        info
+    }
+
+    /// The chain id of the node the session is forked from, `None` if it isn't forked or the node
+    /// can't be reached
+    pub async fn chain_id(&mut self, dispatcher: &ChiselDispatcher) -> Option<u64> {
+        self.fork_info(dispatcher).await?.chain_id
+    }
+
+    /// The output of chisel's `!help` command
+    pub fn help_text(&self) -> &str {
+        &self.help_text
     }
 
     pub async fn handle_chat_request(
-        &self,
+        &mut self,
         dispatcher: &mut ChiselDispatcher,
         line: String,
     ) -> OpenAIResult<()> {
+        // `!chat!` behaves like `!chat`, but always fetches a fresh response
+        let (line, force_refresh) = match line.strip_prefix(FORCE_REFRESH_PREFIX) {
+            Some(rest) => (format!("!chat{rest}"), true),
+            None => (line, false),
+        };
+
+        // Well-known addresses depend on the chain, which has to be known before the profile
+        // borrows the client
+        let fork = self.fork_info(dispatcher).await;
+        let chain_id = fork.as_ref().and_then(|fork| fork.chain_id);
+
+        // `!chat@<profile>` sends just this request with another profile
+        let (line, profile) = match line.strip_prefix(PROFILE_PREFIX) {
+            Some(rest) => {
+                let (name, request) = rest.split_once(' ').unwrap_or((rest, ""));
+                (format!("!chat {request}"), self.profile_by_name(name)?)
+            }
+            None => (line, self.active_profile()),
+        };
+
         println!(
             "{}",
             Paint::blue("\nFetching required command recipe from ChiselGPT\n")
         );
 
-        let (commands, raw_response) = self.get_chat_response(dispatcher, line).await.unwrap();
+        let entries = self.address_book.relevant(&line, chain_id);
+        let (commands, raw_response) = self
+            .get_chat_response(
+                dispatcher,
+                profile,
+                line.clone(),
+                fork.as_ref(),
+                &entries,
+                force_refresh,
+            )
+            .await?;
 
         if commands.len() == 0 {
             eprintln!(
                 "No Commands found for response: {}",
-                Paint::red(raw_response)
+                Paint::red(&raw_response)
             );
 
+            self.transcript.entries.push(TranscriptEntry {
+                request: line,
+                raw_response,
+                ingredients: vec![],
+            });
+            self.save_transcript(dispatcher);
+
+            return Ok(());
+        }
+
+        // Fill in `@name` references, and label the addresses they stand for so that traces show
+        // the names too, unless the session already does
+        let mut used: Vec<(String, String)> = vec![];
+        let commands: Vec<String> = commands
+            .iter()
+            .map(|command| {
+                let (command, entries) = self.address_book.substitute(command, chain_id);
+                for entry in entries {
+                    if !used.contains(&entry) {
+                        used.push(entry);
+                    }
+                }
+                command
+            })
+            .collect();
+        let source = session_source(dispatcher).unwrap_or_default();
+        let commands: Vec<String> = used
+            .into_iter()
+            .filter(|(_, address)| !source.contains(&format!("vm.label({address},")))
+            .map(|(name, address)| format!("vm.label({address}, \"{name}\");"))
+            .chain(commands)
+            .collect();
+
+        // Forks go through the project's RPC aliases rather than whatever URL the model came up
+        // with, a recipe that needs an endpoint that isn't configured can't do what was asked
+        let rewritten: Result<Vec<String>, _> = commands
+            .iter()
+            .enumerate()
+            .map(|(index, command)| {
+                self.rpc_aliases
+                    .rewrite(command)
+                    .map_err(|e| (index + 1, e.to_string()))
+            })
+            .collect();
+        let commands = match rewritten {
+            Ok(commands) => commands,
+            Err((number, e)) => {
+                eprintln!(
+                    "{}",
+                    Paint::red(format!(
+                        "Ingredient {number} forks without a matching RPC endpoint, nothing was dispatched: {e}"
+                    ))
+                );
+
+                self.record_rejected_recipe(
+                    dispatcher,
+                    line,
+                    raw_response,
+                    commands,
+                    &[(number, e)],
+                );
+
+                return Ok(());
+            }
+        };
+
+        // Model output can reach the host through cheatcodes and commands, so it has to get past
+        // the policy before anything else happens
+        if let Some(rejection) = self.config.policy.enforce(&commands) {
+            eprintln!(
+                "{}",
+                Paint::red("The command recipe was rejected, nothing was dispatched")
+            );
+
+            self.record_rejected_recipe(
+                dispatcher,
+                line,
+                raw_response,
+                commands,
+                &[(rejection.ingredient, rejection.reason)],
+            );
+
+            return Ok(());
+        }
+
+        // Compile the recipe against a copy of the session first, so that a recipe that doesn't
+        // compile leaves the session untouched instead of half applied
+        let check = match &dispatcher.session.session_source {
+            Some(source) => check_recipe(source, &commands)
+                .map_err(|e| format!("Failed to compile the recipe: {e}"))?,
+            None => RecipeCheck::Compiled,
+        };
+
+        if let RecipeCheck::Failed(diagnostics) = &check {
+            eprintln!(
+                "{}",
+                Paint::red(format!(
+                    "The command recipe ({} ingredients) doesn't compile, nothing was dispatched",
+                    commands.len()
+                ))
+            );
+
+            for (index, raw_command) in commands.iter().enumerate() {
+                print!("\n{}", self.render_preview(index + 1, raw_command));
+            }
+
+            eprintln!();
+            for diagnostic in diagnostics {
+                eprintln!("{}", Paint::red(diagnostic));
+            }
+
+            let errors: Vec<(usize, String)> = diagnostics
+                .iter()
+                .filter_map(|diagnostic| Some((diagnostic.ingredient?, diagnostic.to_string())))
+                .collect();
+            self.record_rejected_recipe(dispatcher, line, raw_response, commands, &errors);
+
             return Ok(());
         }
 
@@ -97,27 +556,146 @@ impl CompletionClient {
             ))
         );
 
-        for (index, raw_command) in commands.into_iter().enumerate() {
-            let formatted_command = match format_source(&raw_command, self.formatter_config.clone())
-            {
-                Ok(formatted_source) => SolidityHelper::highlight(&formatted_source).into_owned(),
-                Err(_) => SolidityHelper::highlight(&raw_command).into_owned(),
-            };
+        if let RecipeCheck::CompiledUntilCommand(number) = check {
+            println!(
+                "{}",
+                Paint::yellow(format!(
+                    "Ingredient {number} is a chisel command, it and the ingredients after it are only checked as they are dispatched"
+                ))
+            );
+        }
+
+        // Every recipe can be undone by going back to the session from right before it
+        let transcript_len = self.transcript.entries.len();
+        let fork_block_number = self.session_fork_block(dispatcher).await;
+        match self.checkpoints.create_automatic(
+            dispatcher,
+            &line,
+            transcript_len,
+            fork_block_number,
+        ) {
+            Ok(checkpoint) => println!(
+                "{}",
+                Paint::cyan(format!(
+                    "Created checkpoint `{0}`, `!restore {0}` undoes this recipe",
+                    checkpoint.name
+                ))
+            ),
+            Err(e) => eprintln!(
+                "{}",
+                Paint::yellow(format!("No checkpoint was created: {e}"))
+            ),
+        }
 
-            println!("\n{}", Paint::magenta(format!("Ingredient {}:", index + 1)));
-            println!("{}", Paint::green(&formatted_command));
+        let source_before = formatted_session_source(dispatcher)
+            .await
+            .unwrap_or_default();
+        let mut ingredients = Vec::with_capacity(commands.len());
 
+        for (index, raw_command) in commands.into_iter().enumerate() {
+            print!("\n{}", self.render_preview(index + 1, &raw_command));
+
+            let before = executions::trace_source(dispatcher);
             let dispatch_result = dispatcher.dispatch(&raw_command).await;
             log_dispatch_result(&dispatch_result);
+            self.record_execution(before.as_ref(), &raw_command, &dispatch_result)
+                .await;
+
+            if matches!(dispatch_result, DispatchResult::CommandSuccess(_)) {
+                self.external_contracts.record(&raw_command);
+            }
+
+            ingredients.push(IngredientRecord {
+                outcome: (&dispatch_result).into(),
+                source: raw_command,
+                run_code_offset: run_code_len(dispatcher),
+            });
+        }
+
+        let change = SourceChange {
+            request: line.clone(),
+            before: source_before,
+            after: formatted_session_source(dispatcher)
+                .await
+                .unwrap_or_default(),
+        };
+        match change.render() {
+            Some(diff) => println!("\n{}\n{diff}", Paint::cyan("Session source changes:")),
+            None => println!("\n{}", Paint::cyan("The session source didn't change")),
+        }
+        if self.source_changes.len() == MAX_SOURCE_CHANGES {
+            self.source_changes.pop_front();
         }
+        self.source_changes.push_back(change);
+
+        self.transcript.entries.push(TranscriptEntry {
+            request: line,
+            raw_response,
+            ingredients,
+        });
+        self.save_transcript(dispatcher);
 
         Ok(())
     }
 
+    /// Records a recipe that was held back as a whole, `errors` are the reasons given for single
+    /// ingredients by number
+    fn record_rejected_recipe(
+        &mut self,
+        dispatcher: &ChiselDispatcher,
+        request: String,
+        raw_response: String,
+        commands: Vec<String>,
+        errors: &[(usize, String)],
+    ) {
+        let ingredients = commands
+            .into_iter()
+            .enumerate()
+            .map(|(index, source)| {
+                let reasons: Vec<&str> = errors
+                    .iter()
+                    .filter(|(number, _)| *number == index + 1)
+                    .map(|(_, reason)| reason.as_str())
+                    .collect();
+
+                IngredientRecord {
+                    outcome: if reasons.is_empty() {
+                        IngredientOutcome::NotDispatched
+                    } else {
+                        IngredientOutcome::Failure(reasons.join("\n"))
+                    },
+                    source,
+                    run_code_offset: None,
+                }
+            })
+            .collect();
+
+        self.transcript.entries.push(TranscriptEntry {
+            request,
+            raw_response,
+            ingredients,
+        });
+        self.save_transcript(dispatcher);
+    }
+
+    /// Formats and highlights an ingredient, numbered like the diagnostics of [check_recipe]
+    fn render_preview(&self, number: usize, raw_command: &str) -> String {
+        let formatted_command = match format_source(raw_command, self.formatter_config.clone()) {
+            Ok(formatted_source) => SolidityHelper::highlight(&formatted_source).into_owned(),
+            Err(_) => SolidityHelper::highlight(raw_command).into_owned(),
+        };
+
+        render_ingredient(number, raw_command, &formatted_command)
+    }
+
     async fn get_chat_response(
         &self,
         dispatcher: &mut ChiselDispatcher,
+        profile: &ModelProfile,
         request: String,
+        fork: Option<&ForkInfo>,
+        address_book: &[(String, String)],
+        force_refresh: bool,
     ) -> OpenAIResult<(Vec<String>, String)> {
         let chisel_context = dispatcher
             .dispatch_command(ChiselCommand::Source, &[])
@@ -132,20 +710,233 @@ impl CompletionClient {
 
         let chisel_state = chisel_state.unwrap();
 
-        let response = self
-            .client
-            .chat()
-            .create(build_request(
-                request,
-                self.help_text.clone(),
-                chisel_state,
-            )?)
-            .await?;
+        let mut history = self.transcript.recent(HISTORY_LENGTH);
+        let rpc_aliases = self.rpc_aliases.names();
 
-        let raw_response = response.choices.get(0).unwrap().message.content.clone();
+        let context = match (&profile.template, profile.context_window()) {
+            (Some(template), _) => {
+                render_prompt_template(template, self.help_text.clone(), chisel_state, &rpc_aliases)
+            }
+            (None, Some(context_window)) => {
+                // Leave room for the request and the response, then drop the oldest history until
+                // it takes up at most half of what's left
+                let mut budget = context_window
+                    .saturating_sub(profile.max_tokens as usize + estimate_tokens(&request));
+                let history_tokens = |history: &[TranscriptEntry]| -> usize {
+                    history
+                        .iter()
+                        .map(|entry| {
+                            estimate_tokens(&entry.request) + estimate_tokens(&entry.raw_response)
+                        })
+                        .sum()
+                };
+                while !history.is_empty() && history_tokens(history) > budget / 2 {
+                    history = &history[1..];
+                }
+                budget -= history_tokens(history);
+
+                let context = create_context_string(
+                    self.help_text.clone(),
+                    chisel_state.clone(),
+                    &rpc_aliases,
+                );
+                if estimate_tokens(&context) <= budget {
+                    context
+                } else {
+                    create_compact_context_string(
+                        &self.help_text,
+                        &chisel_state,
+                        &rpc_aliases,
+                        budget,
+                    )
+                }
+            }
+            (None, None) => {
+                create_context_string(self.help_text.clone(), chisel_state, &rpc_aliases)
+            }
+        };
+        let source = session_source(dispatcher).unwrap_or_default();
+        let external_contracts = self.external_contracts.describe(&source);
+        let context = [
+            fork.map(fork_context),
+            address_book_context(address_book),
+            external_contracts_context(&external_contracts),
+            executions_context(&self.executions),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(context, |context, section| {
+            format!("{context}\n\n{section}")
+        });
+
+        let raw_response = self
+            .complete(profile, context, history, request, force_refresh)
+            .await?;
 
         let commands = split_commands(&raw_response);
 
         Ok((commands, raw_response))
     }
+
+    /// Explains the session source, or a single definition in it, in natural language. Nothing is
+    /// dispatched, so this is always safe to run.
+    pub async fn handle_explain_request(
+        &self,
+        dispatcher: &ChiselDispatcher,
+        target: Option<&str>,
+        last_failure: Option<&str>,
+    ) -> OpenAIResult<()> {
+        let source = session_source(dispatcher).ok_or("The session has no source to explain")?;
+
+        let mut request = match target {
+            Some(target) => {
+                let definition = find_definition(&source, target).ok_or_else(|| {
+                    format!("No contract, function or variable named `{target}` in the session")
+                })?;
+                format!("Explain `{target}`:\n{definition}")
+            }
+            None => String::from("Explain the session source code"),
+        };
+
+        if let Some(last_failure) = last_failure {
+            request.push_str(&format!(
+                "\n\nAlso explain why the last input failed with:\n{last_failure}"
+            ));
+        }
+
+        println!("{}", Paint::blue("\nFetching explanation from ChiselGPT\n"));
+
+        let explanation = self
+            .complete(
+                self.active_profile(),
+                create_explain_context_string(source),
+                &[],
+                request,
+                false,
+            )
+            .await?;
+
+        println!("{}", explanation.trim());
+
+        Ok(())
+    }
+
+    /// Asks the model to repair an input that failed, returns the corrected commands without
+    /// dispatching them
+    pub async fn handle_fix_request(
+        &self,
+        dispatcher: &ChiselDispatcher,
+        input: &str,
+        error: &str,
+    ) -> OpenAIResult<Vec<String>> {
+        let source = session_source(dispatcher).unwrap_or_default();
+
+        println!("{}", Paint::blue("\nFetching a fix from ChiselGPT\n"));
+
+        let request = format!("Input:\n{input}\n\nError:\n{error}");
+        let raw_response = self
+            .complete(
+                self.active_profile(),
+                create_fix_context_string(
+                    self.help_text.clone(),
+                    source,
+                    &self.rpc_aliases.names(),
+                ),
+                &[],
+                request,
+                false,
+            )
+            .await?;
+
+        let fix = split_commands(&raw_response)
+            .iter()
+            .map(|command| self.rpc_aliases.rewrite(command))
+            .collect::<eyre::Result<Vec<String>>>()?;
+
+        Ok(fix)
+    }
+
+    /// Sends a request to the model, or answers it from the cache
+    async fn complete(
+        &self,
+        profile: &ModelProfile,
+        context: String,
+        history: &[TranscriptEntry],
+        request: String,
+        force_refresh: bool,
+    ) -> OpenAIResult<String> {
+        // The history is part of the prompt, so it has to be part of the key as well
+        let mut prompt = context.clone();
+        for entry in history {
+            prompt.push_str(&entry.request);
+            prompt.push_str(&entry.raw_response);
+        }
+        let cache_key = ResponseCache::key(&profile.cache_id(), &prompt, &request);
+
+        let cached_response = match &self.cache {
+            Some(cache) if !force_refresh => cache.get(&cache_key),
+            _ => None,
+        };
+
+        let raw_response = match cached_response {
+            Some(cached_response) => {
+                println!("{}", Paint::yellow("Using cached response"));
+                cached_response
+            }
+            None => {
+                let raw_response = match profile.backend {
+                    Backend::OpenAi => {
+                        let response = self
+                            .client
+                            .chat()
+                            .create(build_request(profile, request, context, history)?)
+                            .await?;
+
+                        response
+                            .choices
+                            .get(0)
+                            .ok_or("The model returned no response")?
+                            .message
+                            .content
+                            .clone()
+                    }
+                    Backend::Anthropic => {
+                        let response = self
+                            .anthropic
+                            .complete(profile, &context, history, &request)
+                            .await?;
+
+                        if response.stop_reason == Some(StopReason::MaxTokens) {
+                            warn_max_tokens(profile);
+                        }
+
+                        response.into_text()
+                    }
+                    Backend::Ollama => {
+                        let context_window = profile.context_window().unwrap_or_default();
+                        let response = self
+                            .ollama
+                            .complete(profile, context_window, &context, history, &request)
+                            .await?;
+
+                        if response.is_truncated() {
+                            warn_max_tokens(profile);
+                        }
+
+                        response.content
+                    }
+                };
+
+                if let Some(cache) = &self.cache {
+                    if let Err(e) = cache.put(&cache_key, &profile.model, &raw_response) {
+                        eprintln!("{}", Paint::red(format!("Failed to cache response: {e}")));
+                    }
+                }
+
+                raw_response
+            }
+        };
+
+        Ok(raw_response)
+    }
 }
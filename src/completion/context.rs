@@ -1,6 +1,150 @@
 use super::foundry_interface::FOUNDRY_INTERFACE;
+use super::{executions::Executions, transcript::IngredientOutcome};
+use crate::helpers::{
+    address_book::well_known, command_helper::strip_ansi, external_contracts::ExternalContract,
+    fork::ForkInfo,
+};
 
-pub fn create_context_string(help_text: String, chisel_context: String) -> String {
+/// Renders the `!chat` prompt of a profile with a prompt template, `{help}` and `{source}` are
+/// replaced with chisel's `!help` output and the session source, `{cheatcodes}` with the cheatcode
+/// interface and `{rpc_endpoints}` with the RPC aliases forks have to use
+pub fn render_prompt_template(
+    template: &str,
+    help_text: String,
+    chisel_context: String,
+    rpc_aliases: &[String],
+) -> String {
+    template
+        .replace("{help}", &help_text)
+        .replace("{source}", &chisel_context)
+        .replace("{cheatcodes}", FOUNDRY_INTERFACE)
+        .replace("{rpc_endpoints}", &rpc_endpoints_context(rpc_aliases))
+}
+
+/// Tells the model to fork through the project's `rpc_endpoints` aliases rather than URLs
+pub fn rpc_endpoints_context(rpc_aliases: &[String]) -> String {
+    if rpc_aliases.is_empty() {
+        return String::from("The project has no RPC endpoints configured, so forking is not possible. Never write out RPC URLs.");
+    }
+
+    format!(
+        "To fork, use one of the RPC endpoint aliases of the project, i.e. '!f {alias}' or 'vm.createSelectFork(\"{alias}\")'. Never write out RPC URLs. The aliases are: {}.",
+        rpc_aliases.join(", "),
+        alias = rpc_aliases[0]
+    )
+}
+
+/// Lists the address book entries a request mentions, recipes refer to them as `@name`
+pub fn address_book_context(entries: &[(String, String)]) -> Option<String> {
+    let (example, _) = entries.first()?;
+    let entries = entries
+        .iter()
+        .map(|(name, address)| format!("@{name}: {address}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(format!(
+        "Address book, refer to these addresses by name, i.e. '@{example}', and the address is filled in:\n{entries}"
+    ))
+}
+
+/// Describes the chain the session is forked from, so that recipes use the addresses of that chain
+pub fn fork_context(fork: &ForkInfo) -> String {
+    let mut context = format!(
+        "The session is forked from {}",
+        match &fork.alias {
+            Some(alias) => format!("the rpc_endpoints alias '{alias}'"),
+            None => "an RPC URL".to_string(),
+        }
+    );
+
+    match (fork.chain_name(), fork.chain_id) {
+        (Some(name), Some(chain_id)) => {
+            context.push_str(&format!(", {name} (chain id {chain_id})"))
+        }
+        (None, Some(chain_id)) => context.push_str(&format!(", chain id {chain_id}")),
+        _ => {}
+    }
+    if let Some(block_number) = fork.block_number {
+        context.push_str(&format!(", at block {block_number}"));
+    }
+    context.push_str(
+        ". Only use addresses that exist on this chain, contracts have other addresses on other chains.",
+    );
+
+    let well_known = fork.chain_id.map(well_known).unwrap_or_default();
+    if !well_known.is_empty() {
+        let contracts = well_known
+            .iter()
+            .map(|(name, address)| format!("{name}: {address}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        context.push_str(&format!(" Well-known contracts on this chain: {contracts}"));
+    }
+
+    context
+}
+
+/// Lists the contracts fetched with `!fetch`, so that recipes call their real functions
+pub fn external_contracts_context(
+    contracts: &[(&ExternalContract, Vec<String>)],
+) -> Option<String> {
+    if contracts.is_empty() {
+        return None;
+    }
+
+    let contracts = contracts
+        .iter()
+        .map(|(contract, functions)| {
+            let functions: String = functions
+                .iter()
+                .map(|function| format!("\n  {function}"))
+                .collect();
+            format!("{} at {}:{functions}", contract.name, contract.address)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(format!(
+        "Available external contracts, their interfaces are already part of the session, call them as i.e. 'Name(address).function(...)' and only use the functions listed:\n{contracts}"
+    ))
+}
+
+/// Lists what came of the inputs dispatched most recently, so that requests can build on return
+/// values and ask about reverts
+pub fn executions_context(executions: &Executions) -> Option<String> {
+    if executions.is_empty() {
+        return None;
+    }
+
+    let executions = executions
+        .iter()
+        .map(|execution| {
+            let outcome = match &execution.outcome {
+                IngredientOutcome::Success(Some(output)) => strip_ansi(output),
+                IngredientOutcome::Success(None) => "ok".to_string(),
+                IngredientOutcome::Failure(e) => format!("failed: {}", strip_ansi(e)),
+                IngredientOutcome::NotDispatched => "not dispatched".to_string(),
+            };
+            let trace = match &execution.trace {
+                Some(trace) => format!("\ntrace: {trace}"),
+                None => String::new(),
+            };
+            format!("> {}\n{outcome}{trace}", execution.input)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(format!(
+        "Results of the most recent inputs in the session, oldest first:\n{executions}"
+    ))
+}
+
+pub fn create_context_string(
+    help_text: String,
+    chisel_context: String,
+    rpc_aliases: &[String],
+) -> String {
     String::from("
   This prompt is designed to help you convert natural language text into Chisel commands and/or Solidity code. Follow the guidelines provided below and use the examples as a reference for your conversions:
 
@@ -23,6 +167,8 @@ pub fn create_context_string(help_text: String, chisel_context: String) -> Strin
     &FOUNDRY_INTERFACE +
   "
 
+  " + &rpc_endpoints_context(rpc_aliases) + "
+
   Please provide a clear and concise output to perform the intended action in a blockchain environment using Chisel and Solidity.
 
   Examples of expected output:
@@ -69,7 +215,7 @@ pub fn create_context_string(help_text: String, chisel_context: String) -> Strin
   6. Input: !chat fork mainnet and fetch the WETH contract
      Output:
      ##START##
-     !f https://mainnet.infura.io/v3/84842078b09946638c03157f83405213
+     !f mainnet
      !fe 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2 WETH
      WETH weth = WETH(0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2);
      ##END##
@@ -89,3 +235,157 @@ pub fn create_context_string(help_text: String, chisel_context: String) -> Strin
   Remember it is extremely important you use '##START##' to mark the start of commands and '##END##' to mark the end of the commands.
   "
 }
+
+pub fn create_explain_context_string(chisel_context: String) -> String {
+    String::from("
+  You are helping a developer understand Solidity code inside a Chisel REPL session. Chisel wraps the session in a contract called REPL, single line statements are placed inside of its run() method and definitions are placed inside of the contract or above it.
+
+  Explain the requested code in clear, concise natural language. Describe what it does, how the pieces interact and anything surprising, such as security issues or gas costs. Do not write new code unless it is needed to illustrate a point, and never output '##START##' or '##END##' markers.
+
+  Here is the current Chisel session source code: ") +
+    &chisel_context +
+    &FOUNDRY_INTERFACE
+}
+
+pub fn create_fix_context_string(
+    help_text: String,
+    chisel_context: String,
+    rpc_aliases: &[String],
+) -> String {
+    String::from("
+  You are repairing an input that failed inside a Chisel REPL session. You are given the input, the error it produced and the session source code the input was added to.
+
+  Reply with the corrected input only, keep it as close to the original as possible and do not repeat code that is already in the session. Use '##START##' to mark the start of the corrected input and '##END##' to mark the end of it.
+
+  Here is the documentation for Chisel commands: ") +
+    &help_text + "
+
+  Here is the current Chisel session source code: " +
+    &chisel_context +
+    &FOUNDRY_INTERFACE +
+  "
+
+  " + &rpc_endpoints_context(rpc_aliases) + "
+
+  Example:
+
+  Input:
+  uint256 x = 1
+  Error:
+  Expected ';' but found end of file
+  Output: ##START##uint256 x = 1;##END##
+  "
+}
+
+/// Roughly the number of tokens in a text, models average around four characters per token
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len() / 4 + 1
+}
+
+/// A shorter version of [create_context_string] for models with small context windows. It keeps
+/// a single example, leaves out the cheatcode interface and colours, and keeps only as much of the
+/// end of the session source as fits in `budget` tokens.
+pub fn create_compact_context_string(
+    help_text: &str,
+    chisel_context: &str,
+    rpc_aliases: &[String],
+    budget: usize,
+) -> String {
+    let help_text = strip_ansi(help_text);
+    let commands: Vec<&str> = help_text
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('!'))
+        .collect();
+
+    let prompt = |source: &str| {
+        format!(
+            "Convert the request into Chisel commands and Solidity code for a Chisel REPL session. Put the code between '##START##' and '##END##'. Chisel commands start with '!'. Contracts and functions are added to the REPL contract, single lines are added to its run() function. Reuse existing variables, new variable names must be unique.
+
+Chisel commands:
+{}
+
+{}
+
+Session source:
+{source}
+
+Example:
+Input: !chat deploy a counter and increment it
+Output:
+##START##
+contract Counter {{ uint256 public count; function increment() public {{ count++; }} }}
+Counter counter = new Counter();
+counter.increment();
+##END##
+",
+            commands.join("\n"),
+            rpc_endpoints_context(rpc_aliases)
+        )
+    };
+
+    let source = strip_ansi(chisel_context);
+    let available = budget.saturating_sub(estimate_tokens(&prompt("")));
+
+    // Keep the most recent code, earlier code is the least likely to matter for the request
+    let mut kept: Vec<&str> = Vec::new();
+    let mut used = 0;
+    for line in source.lines().rev() {
+        used += estimate_tokens(line);
+        if used > available {
+            kept.push("// ... earlier code omitted");
+            break;
+        }
+        kept.push(line);
+    }
+    kept.reverse();
+
+    prompt(&kept.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_the_end_of_the_source_within_budget() {
+        let source = (0..200)
+            .map(|i| format!("uint256 value{i} = {i};"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let help = "\u{1b}[32mGeneral\n\t!help | !h - Display all commands\u{1b}[0m";
+
+        let aliases = vec!["mainnet".to_string()];
+
+        let full = create_context_string(help.to_string(), source.clone(), &aliases);
+        let compact = create_compact_context_string(help, &source, &aliases, 1024);
+
+        assert!(estimate_tokens(&full) > 1024);
+        assert!(estimate_tokens(&compact) <= 1024);
+        assert!(compact.contains("!help | !h - Display all commands"));
+        assert!(!compact.contains('\u{1b}'));
+        assert!(compact.contains("// ... earlier code omitted\n"));
+        assert!(compact.contains("uint256 value199 = 199;"));
+        assert!(!compact.contains("uint256 value0 = 0;"));
+    }
+
+    #[test]
+    fn it_describes_the_fork() {
+        let polygon = ForkInfo {
+            alias: Some("polygon".to_string()),
+            chain_id: Some(137),
+            block_number: Some(50_000_000),
+        };
+        let context = fork_context(&polygon);
+
+        assert!(context.starts_with(
+            "The session is forked from the rpc_endpoints alias 'polygon', Polygon (chain id 137), at block 50000000."
+        ));
+        assert!(context.contains("USDC: 0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359"));
+        assert!(!context.contains("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"));
+
+        let unknown = fork_context(&ForkInfo::default());
+        assert!(unknown.starts_with("The session is forked from an RPC URL. Only use"));
+        assert!(!unknown.contains("Well-known"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+
+use chisel::prelude::{ChiselDispatcher, SessionSource};
+use foundry_evm::trace::TraceKind;
+
+use super::transcript::IngredientOutcome;
+use crate::helpers::{command_helper::strip_ansi, policy::capabilities};
+
+/// The number of dispatch results that are kept for the prompt
+const CAPACITY: usize = 8;
+
+/// Traces are cut off after this many lines, a deep call tree would crowd out the rest of the
+/// prompt
+const MAX_TRACE_LINES: usize = 30;
+
+/// Cheatcodes that create forks, running them again queries the RPC endpoint again
+const FORK_CHEATCODES: &[&str] = &["createFork", "createSelectFork", "rollFork"];
+
+/// An input that was dispatched, by the user or as an ingredient, and what came of it
+#[derive(Debug, Clone)]
+pub struct Execution {
+    pub input: String,
+    pub outcome: IngredientOutcome,
+    /// Summary of the call trace, if traces are enabled
+    pub trace: Option<String>,
+}
+
+/// The most recent dispatch results of the session, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct Executions {
+    entries: VecDeque<Execution>,
+}
+
+impl Executions {
+    /// Adds an execution, dropping the oldest one once there are [CAPACITY]
+    pub fn push(&mut self, execution: Execution) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(execution);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Execution> {
+        self.entries.iter()
+    }
+}
+
+/// The session to trace the next input on top of, see [trace]. Copying the session is only worth
+/// it when traces are enabled.
+pub fn trace_source(dispatcher: &ChiselDispatcher) -> Option<SessionSource> {
+    dispatcher
+        .session
+        .session_source
+        .as_ref()
+        .filter(|source| source.config.traces)
+        .cloned()
+}
+
+/// Runs `input` on top of `source`, the session as it was before `input` was dispatched, and
+/// summarises the call trace. Chisel prints traces rather than returning them, so this executes
+/// the input a second time. `None` unless traces are enabled (`-v`), for inputs that don't get as
+/// far as being executed, and for inputs that can't be run twice without doing twice what they do
+/// outside of the EVM.
+pub async fn trace(source: &SessionSource, input: &str) -> Option<String> {
+    if !source.config.traces || !reruns_safely(input) {
+        return None;
+    }
+
+    let (mut source, _) = source.clone_with_new_line(input.to_string()).ok()?;
+    let (_, mut result) = source.execute().await.ok()?;
+
+    let decoder = ChiselDispatcher::decode_traces(&source.config, &mut result).ok()?;
+    let mut rendered = String::new();
+    for (kind, trace) in &mut result.traces {
+        if matches!(kind, TraceKind::Setup | TraceKind::Execution) {
+            decoder.decode(trace).await;
+            rendered.push_str(&trace.to_string());
+        }
+    }
+
+    Some(summarise(&rendered, result.success, result.gas_used))
+}
+
+/// Whether `input` only touches the EVM, so that running it again only costs time. Inputs that use
+/// the shell, the file system or the environment, whatever the policy allows, or that create a
+/// fork are not run again.
+fn reruns_safely(input: &str) -> bool {
+    capabilities(input).is_empty() && !FORK_CHEATCODES.iter().any(|name| input.contains(name))
+}
+
+/// The outcome and gas usage followed by the rendered call trace without colours, cut off after
+/// [MAX_TRACE_LINES]
+fn summarise(rendered: &str, success: bool, gas_used: u64) -> String {
+    let status = if success { "succeeded" } else { "reverted" };
+    let mut summary = format!("{status}, {gas_used} gas");
+
+    let rendered = strip_ansi(rendered);
+    let lines: Vec<&str> = rendered
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    for line in lines.iter().take(MAX_TRACE_LINES) {
+        summary.push('\n');
+        summary.push_str(line);
+    }
+    if lines.len() > MAX_TRACE_LINES {
+        summary.push_str(&format!(
+            "\n... {} more lines",
+            lines.len() - MAX_TRACE_LINES
+        ));
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execution(input: &str) -> Execution {
+        Execution {
+            input: input.to_string(),
+            outcome: IngredientOutcome::Success(None),
+            trace: None,
+        }
+    }
+
+    #[test]
+    fn it_keeps_the_most_recent_executions() {
+        let mut executions = Executions::default();
+        for index in 0..CAPACITY + 2 {
+            executions.push(execution(&format!("uint256 x{index} = {index};")));
+        }
+
+        let inputs: Vec<&str> = executions.iter().map(|e| e.input.as_str()).collect();
+        assert_eq!(inputs.len(), CAPACITY);
+        assert_eq!(inputs[0], "uint256 x2 = 2;");
+        assert_eq!(inputs[CAPACITY - 1], "uint256 x9 = 9;");
+    }
+
+    #[test]
+    fn it_only_copies_the_session_when_tracing() {
+        let mut dispatcher = ChiselDispatcher::default();
+        dispatcher.session.session_source = Some(SessionSource::default());
+        assert!(trace_source(&dispatcher).is_none());
+
+        dispatcher
+            .session
+            .session_source
+            .as_mut()
+            .unwrap()
+            .config
+            .traces = true;
+        assert!(trace_source(&dispatcher).is_some());
+    }
+
+    #[tokio::test]
+    async fn it_only_traces_inputs_that_can_run_again() {
+        let mut source = SessionSource::default();
+        assert_eq!(trace(&source, "uint256 x = 1;").await, None);
+
+        source.config.traces = true;
+        for input in [
+            "vm.writeFile(\"out.txt\", \"data\");",
+            "bytes memory out = vm.ffi(cmd);",
+            "vm.setEnv(\"KEY\", \"value\");",
+            "uint256 fork = vm.createSelectFork(\"mainnet\");",
+        ] {
+            assert!(!reruns_safely(input), "{input}");
+            assert_eq!(trace(&source, input).await, None);
+        }
+        assert!(reruns_safely("vm.deal(address(this), 1 ether);"));
+    }
+
+    #[test]
+    fn it_summarises_traces() {
+        let rendered = "\x1b[32mTraces:\x1b[0m\n  [2213] REPL::run()\n    ├─ [0] Token::transfer(0x01, 1) \n    │   └─ ← \"insufficient balance\"\n\n    └─ ← ()\n";
+        assert_eq!(
+            summarise(rendered, false, 2213),
+            "reverted, 2213 gas\nTraces:\n  [2213] REPL::run()\n    ├─ [0] Token::transfer(0x01, 1) \n    │   └─ ← \"insufficient balance\"\n    └─ ← ()"
+        );
+
+        let long: String = (0..MAX_TRACE_LINES + 5)
+            .map(|n| format!("line {n}\n"))
+            .collect();
+        assert!(summarise(&long, true, 1).ends_with("line 29\n... 5 more lines"));
+    }
+}
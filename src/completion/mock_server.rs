@@ -0,0 +1,87 @@
+//! A stand-in HTTP server for testing the backends without network access
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+/// A response served by the mock server
+pub struct MockResponse {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl MockResponse {
+    pub fn json(status: &'static str, body: impl ToString) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+}
+
+/// Serves the responses in order, one per connection. Returns the base URL and a handle that
+/// resolves to the requests that were received.
+pub async fn mock_server(responses: Vec<MockResponse>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let mut requests = Vec::with_capacity(responses.len());
+
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(headers_end) = text.find("\r\n\r\n") {
+                    let content_length: usize = text[..headers_end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse().ok())?
+                        })
+                        .unwrap_or(0);
+
+                    if request.len() >= headers_end + 4 + content_length {
+                        break;
+                    }
+                }
+
+                if read == 0 {
+                    break;
+                }
+            }
+
+            let reply = format!(
+                "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                response.status,
+                response.content_type,
+                response.body.len(),
+                response.body
+            );
+            socket.write_all(reply.as_bytes()).await.unwrap();
+
+            requests.push(String::from_utf8_lossy(&request).to_string());
+        }
+
+        requests
+    });
+
+    (base_url, handle)
+}
+
+/// The body of a request received by the mock server
+pub fn request_body(request: &str) -> serde_json::Value {
+    let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+    serde_json::from_str(body).unwrap()
+}
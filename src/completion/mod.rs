@@ -1,3 +1,11 @@
+pub mod anthropic;
+pub mod cache;
 pub mod complete;
 mod context;
-mod foundry_interface;
+pub mod executions;
+pub mod foundry_interface;
+#[cfg(test)]
+pub(crate) mod mock_server;
+pub mod ollama;
+pub mod profile;
+pub mod transcript;
@@ -0,0 +1,289 @@
+use std::{env, error::Error};
+
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{profile::ModelProfile, transcript::TranscriptEntry};
+
+/// Where Ollama listens unless `OLLAMA_HOST` says otherwise
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+type OllamaResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Debug, Serialize)]
+struct Message<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatOptions {
+    temperature: f32,
+    /// The maximum number of tokens to generate
+    num_predict: u16,
+    /// The size of the context window, Ollama falls back to a small default when it isn't set
+    num_ctx: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<Message<'a>>,
+    stream: bool,
+    options: ChatOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseMessage {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawChatResponse {
+    message: ResponseMessage,
+    done_reason: Option<String>,
+}
+
+/// The reply to a chat request
+#[derive(Debug)]
+pub struct ChatResponse {
+    pub content: String,
+    /// Why generation stopped, `length` when the response was cut off by `max_tokens`
+    pub done_reason: Option<String>,
+}
+
+impl ChatResponse {
+    pub fn is_truncated(&self) -> bool {
+        self.done_reason.as_deref() == Some("length")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalModel {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<LocalModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Client for a local Ollama server, nothing leaves the machine
+#[derive(Debug, Clone)]
+pub struct OllamaClient {
+    http: Client,
+    base_url: String,
+}
+
+impl OllamaClient {
+    /// Creates a client for the server at `OLLAMA_HOST`, which may leave out the scheme like
+    /// Ollama itself allows
+    pub fn new() -> Self {
+        let host = env::var("OLLAMA_HOST").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        let base_url = if host.contains("://") {
+            host
+        } else {
+            format!("http://{host}")
+        };
+
+        Self::with_base_url(base_url)
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        Self {
+            http: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The names of the models that have been pulled, i.e. `llama3:latest`
+    pub async fn models(&self) -> OllamaResult<Vec<String>> {
+        let response = self
+            .http
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .map_err(|e| self.connection_error(e))?;
+
+        let tags: TagsResponse = response.error_for_status()?.json().await?;
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+
+    /// Sends a request with the given context window
+    pub async fn complete(
+        &self,
+        profile: &ModelProfile,
+        context_window: usize,
+        context: &str,
+        history: &[TranscriptEntry],
+        request: &str,
+    ) -> OllamaResult<ChatResponse> {
+        let mut messages = vec![Message {
+            role: "system",
+            content: context,
+        }];
+        for entry in history {
+            messages.push(Message {
+                role: "user",
+                content: &entry.request,
+            });
+            messages.push(Message {
+                role: "assistant",
+                content: &entry.raw_response,
+            });
+        }
+        messages.push(Message {
+            role: "user",
+            content: request,
+        });
+
+        let body = ChatRequest {
+            model: &profile.model,
+            messages,
+            stream: false,
+            options: ChatOptions {
+                temperature: profile.temperature,
+                num_predict: profile.max_tokens,
+                num_ctx: context_window,
+            },
+        };
+
+        let response = self
+            .http
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| self.connection_error(e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let error = serde_json::from_str::<ErrorResponse>(&body)
+                .map(|response| response.error)
+                .unwrap_or(body);
+
+            // Point at the models that are available instead
+            if status == StatusCode::NOT_FOUND {
+                let models = self.models().await.unwrap_or_default();
+                return Err(if models.is_empty() {
+                    format!("{error}, no models have been pulled (`ollama pull <model>`)")
+                } else {
+                    format!("{error}, the local models are: {}", models.join(", "))
+                }
+                .into());
+            }
+
+            return Err(format!("Ollama error ({status}): {error}").into());
+        }
+
+        let response: RawChatResponse = response.json().await?;
+
+        Ok(ChatResponse {
+            content: response.message.content,
+            done_reason: response.done_reason,
+        })
+    }
+
+    fn connection_error(&self, error: reqwest::Error) -> Box<dyn Error> {
+        format!(
+            "Failed to reach Ollama at {} ({error}), is `ollama serve` running?",
+            self.base_url
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::completion::mock_server::{mock_server, request_body, MockResponse};
+
+    fn profile() -> ModelProfile {
+        ModelProfile {
+            model: "llama3".to_string(),
+            ..ModelProfile::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn it_sends_chat_requests() {
+        let (base_url, server) = mock_server(vec![MockResponse::json(
+            "200 OK",
+            json!({
+                "model": "llama3",
+                "message": {"role": "assistant", "content": "##START##\nuint256 a = 1;\n##END##"},
+                "done": true,
+                "done_reason": "stop"
+            }),
+        )])
+        .await;
+
+        let response = OllamaClient::with_base_url(base_url)
+            .complete(&profile(), 4096, "You are ChiselGPT", &[], "!chat set a to 1")
+            .await
+            .unwrap();
+
+        assert_eq!(response.content, "##START##\nuint256 a = 1;\n##END##");
+        assert!(!response.is_truncated());
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /api/chat "));
+
+        let body = request_body(&requests[0]);
+        assert_eq!(body["model"], "llama3");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][0]["content"], "You are ChiselGPT");
+        assert_eq!(body["messages"][1]["content"], "!chat set a to 1");
+        assert_eq!(body["options"]["num_ctx"], 4096);
+        assert_eq!(body["options"]["num_predict"], 512);
+    }
+
+    #[tokio::test]
+    async fn it_discovers_models() {
+        let (base_url, server) = mock_server(vec![MockResponse::json(
+            "200 OK",
+            json!({"models": [{"name": "llama3:latest", "size": 4661224676u64}, {"name": "codellama:7b"}]}),
+        )])
+        .await;
+
+        let models = OllamaClient::with_base_url(base_url)
+            .models()
+            .await
+            .unwrap();
+
+        assert_eq!(models, vec!["llama3:latest", "codellama:7b"]);
+        assert!(server.await.unwrap()[0].starts_with("GET /api/tags "));
+    }
+
+    #[tokio::test]
+    async fn it_lists_local_models_when_the_model_is_missing() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::json(
+                "404 Not Found",
+                json!({"error": "model \"llama3\" not found, try pulling it first"}),
+            ),
+            MockResponse::json("200 OK", json!({"models": [{"name": "codellama:7b"}]})),
+        ])
+        .await;
+
+        let error = OllamaClient::with_base_url(base_url)
+            .complete(&profile(), 2048, "", &[], "!chat hi")
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "model \"llama3\" not found, try pulling it first, the local models are: codellama:7b"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+
+use crate::helpers::policy::Policy;
+
+/// Name of the file, in the project root, that holds ChiselGPT's configuration
+pub const CONFIG_FILE_NAME: &str = "chisel_gpt.toml";
+
+/// Name of the profile used when none are configured
+const DEFAULT_PROFILE_NAME: &str = "default";
+
+/// The context window of Ollama models unless configured otherwise, Ollama's own default
+const OLLAMA_CONTEXT_WINDOW: usize = 2048;
+
+/// The API a profile's requests are sent to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    OpenAi,
+    /// The Messages API, authenticated with `ANTHROPIC_API_KEY`
+    Anthropic,
+    /// A local Ollama server at `OLLAMA_HOST`
+    Ollama,
+}
+
+/// A model along with the settings requests to it are made with
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelProfile {
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u16,
+    pub backend: Backend,
+    /// The number of tokens the model can take in, prompts are shortened to fit when set
+    pub context_window: Option<usize>,
+    /// Path of a file, relative to the project root, that replaces the built-in `!chat` prompt,
+    /// see [render_prompt_template](super::context::render_prompt_template)
+    pub prompt_template: Option<String>,
+    /// The contents of the prompt template, read when the configuration is loaded
+    #[serde(skip)]
+    pub template: Option<String>,
+}
+
+impl Default for ModelProfile {
+    fn default() -> Self {
+        Self {
+            model: "gpt-3.5-turbo".to_string(),
+            // To get close-to deterministic results
+            temperature: 0.0,
+            max_tokens: 512,
+            backend: Backend::default(),
+            context_window: None,
+            prompt_template: None,
+            template: None,
+        }
+    }
+}
+
+impl ModelProfile {
+    /// The context window prompts have to fit in, if it is small enough to matter
+    pub fn context_window(&self) -> Option<usize> {
+        self.context_window
+            .or((self.backend == Backend::Ollama).then_some(OLLAMA_CONTEXT_WINDOW))
+    }
+
+    /// Identifies everything about the profile that changes the response to a prompt, used as part
+    /// of the cache key
+    pub fn cache_id(&self) -> String {
+        format!(
+            "{:?}/{}/{}/{}",
+            self.backend, self.model, self.temperature, self.max_tokens
+        )
+    }
+}
+
+/// ChiselGPT's configuration, read from `chisel_gpt.toml` in the project root, i.e.
+///
+/// ```toml
+/// default_profile = "cheap"
+///
+/// [profiles.cheap]
+/// model = "gpt-3.5-turbo"
+///
+/// [profiles.strong]
+/// model = "gpt-4"
+/// temperature = 0.2
+/// max_tokens = 2048
+/// prompt_template = "prompts/contracts.txt"
+///
+/// [policy]
+/// file_system = "block"
+///
+/// [address_book]
+/// alice = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChiselGptConfig {
+    /// The profile used unless another one is selected, defaults to the `default` profile or the
+    /// first one by name
+    pub default_profile: Option<String>,
+    pub profiles: BTreeMap<String, ModelProfile>,
+    /// What model generated ingredients are allowed to do
+    pub policy: Policy,
+    /// Addresses requests can refer to by name
+    pub address_book: BTreeMap<String, String>,
+}
+
+impl ChiselGptConfig {
+    /// Loads the configuration of the project, a project without one gets a single `default`
+    /// profile
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(CONFIG_FILE_NAME);
+        let mut config = if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+            Self::parse(&contents).wrap_err_with(|| format!("Invalid {}", path.display()))?
+        } else {
+            Self::parse("")?
+        };
+
+        for (name, profile) in config.profiles.iter_mut() {
+            if let Some(template) = &profile.prompt_template {
+                let template_path = root.join(template);
+                profile.template =
+                    Some(fs::read_to_string(&template_path).wrap_err_with(|| {
+                        format!(
+                            "Failed to read the prompt template of profile `{name}` from {}",
+                            template_path.display()
+                        )
+                    })?);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Parses the configuration and fills in the default profile
+    fn parse(contents: &str) -> Result<Self> {
+        let mut config: Self = toml::from_str(contents)?;
+
+        if config.profiles.is_empty() {
+            config
+                .profiles
+                .insert(DEFAULT_PROFILE_NAME.to_string(), ModelProfile::default());
+        }
+
+        let default_profile = match config.default_profile.take() {
+            Some(name) if config.profiles.contains_key(&name) => name,
+            Some(name) => return Err(eyre!("The default profile `{name}` is not configured")),
+            None if config.profiles.contains_key(DEFAULT_PROFILE_NAME) => {
+                DEFAULT_PROFILE_NAME.to_string()
+            }
+            // Profiles can't be empty at this point
+            None => config.profiles.keys().next().unwrap().clone(),
+        };
+        config.default_profile = Some(default_profile);
+
+        Ok(config)
+    }
+
+    /// The name of the profile used unless another one is selected
+    pub fn default_profile(&self) -> &str {
+        self.default_profile
+            .as_deref()
+            .unwrap_or(DEFAULT_PROFILE_NAME)
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&ModelProfile> {
+        self.profiles.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_defaults_to_a_single_profile() {
+        let config = ChiselGptConfig::parse("").unwrap();
+
+        assert_eq!(config.default_profile(), "default");
+        assert_eq!(config.profile("default").unwrap(), &ModelProfile::default());
+        assert_eq!(config.policy, Policy::default());
+    }
+
+    #[test]
+    fn it_parses_profiles() {
+        let config = ChiselGptConfig::parse(
+            r#"
+[profiles.cheap]
+model = "gpt-3.5-turbo"
+
+[profiles.strong]
+model = "gpt-4"
+temperature = 0.2
+max_tokens = 2048
+backend = "openai"
+
+[profiles.claude]
+model = "claude-3-5-sonnet-latest"
+backend = "anthropic"
+
+[profiles.local]
+model = "llama3"
+backend = "ollama"
+"#,
+        )
+        .unwrap();
+
+        // Without `default_profile` the first profile by name is used
+        assert_eq!(config.default_profile(), "cheap");
+
+        let strong = config.profile("strong").unwrap();
+        assert_eq!(strong.model, "gpt-4");
+        assert_eq!(strong.max_tokens, 2048);
+        assert_eq!(strong.backend, Backend::OpenAi);
+        assert_eq!(
+            config.profile("claude").unwrap().backend,
+            Backend::Anthropic
+        );
+        assert_eq!(
+            config.profile("local").unwrap().context_window(),
+            Some(2048)
+        );
+        assert_eq!(strong.context_window(), None);
+        assert!(config.profile("default").is_none());
+    }
+
+    #[test]
+    fn it_rejects_unknown_default_profiles() {
+        assert!(ChiselGptConfig::parse(
+            "default_profile = \"strong\"\n[profiles.cheap]\nmodel = \"gpt-3.5-turbo\""
+        )
+        .is_err());
+    }
+}
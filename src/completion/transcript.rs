@@ -0,0 +1,271 @@
+use std::{fs, path::PathBuf};
+
+use chisel::prelude::DispatchResult;
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::data_dir::cache_dir;
+
+/// Name of the directory, inside of the ChiselGPT cache directory, that holds transcripts. They
+/// belong to the cached chisel sessions and are cleared along with them.
+const TRANSCRIPTS_DIR_NAME: &str = "transcripts";
+
+/// The outcome of dispatching a single ingredient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IngredientOutcome {
+    Success(Option<String>),
+    Failure(String),
+    /// Held back because another ingredient of the recipe was rejected
+    NotDispatched,
+}
+
+impl From<&DispatchResult> for IngredientOutcome {
+    fn from(result: &DispatchResult) -> Self {
+        match result {
+            DispatchResult::Success(msg) | DispatchResult::CommandSuccess(msg) => {
+                Self::Success(msg.clone())
+            }
+            DispatchResult::UnrecognizedCommand(e) => Self::Failure(e.to_string()),
+            DispatchResult::SolangParserFailed(e) => Self::Failure(format!("{e:?}")),
+            DispatchResult::FileIoError(e) => Self::Failure(e.to_string()),
+            DispatchResult::CommandFailed(msg) | DispatchResult::Failure(Some(msg)) => {
+                Self::Failure(msg.clone())
+            }
+            DispatchResult::Failure(None) => Self::Failure("Unknown error".to_string()),
+        }
+    }
+}
+
+/// An ingredient of a recipe, along with the outcome of dispatching it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngredientRecord {
+    pub source: String,
+    pub outcome: IngredientOutcome,
+    /// Length of the session's run code once the ingredient was dispatched, exports place the
+    /// assertions derived from it there. Transcripts saved before it was recorded don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_code_offset: Option<usize>,
+}
+
+/// A single `!chat` request and everything that came out of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub request: String,
+    pub raw_response: String,
+    pub ingredients: Vec<IngredientRecord>,
+}
+
+/// The chat history of a session, persisted next to the cached chisel session so that it can be
+/// restored when the session is loaded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    /// The path of the transcript for the session with the given id
+    pub fn path(id: &str) -> Option<PathBuf> {
+        Some(Self::dir()?.join(format!("chisel-{id}.json")))
+    }
+
+    /// The directory transcripts are written to
+    fn dir() -> Option<PathBuf> {
+        cache_dir().map(|dir| dir.join(TRANSCRIPTS_DIR_NAME))
+    }
+
+    /// Removes the transcripts of every session, used when the cached sessions are cleared
+    pub fn clear_all() -> std::io::Result<()> {
+        match Self::dir() {
+            Some(dir) if dir.exists() => fs::remove_dir_all(dir),
+            _ => Ok(()),
+        }
+    }
+
+    /// Loads the transcript of the session with the given id, sessions that were saved without any
+    /// chat requests have no transcript.
+    pub fn load(id: &str) -> Option<Self> {
+        let contents = fs::read_to_string(Self::path(id)?).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Writes the transcript for the session with the given id
+    pub fn save(&self, id: &str) -> std::io::Result<()> {
+        let path = Self::path(id).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Failed to resolve the cache directory",
+            )
+        })?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// The most recent `count` entries, oldest first
+    pub fn recent(&self, count: usize) -> &[TranscriptEntry] {
+        &self.entries[self.entries.len().saturating_sub(count)..]
+    }
+
+    /// Annotates the session source with the requests that produced it, ingredients that don't
+    /// appear in the source (chisel commands, failures) are listed after it.
+    pub fn interleave(&self, source: &str) -> String {
+        let mut annotations: Vec<(&str, String)> = Vec::new();
+        let mut unplaced = Vec::new();
+
+        for (request_index, entry) in self.entries.iter().enumerate() {
+            for (index, ingredient) in entry.ingredients.iter().enumerate() {
+                let first_line = ingredient.source.lines().next().unwrap_or_default().trim();
+                let label = format!(
+                    "// [{}] {} (ingredient {})",
+                    request_index + 1,
+                    entry.request,
+                    index + 1
+                );
+
+                let placed = matches!(ingredient.outcome, IngredientOutcome::Success(_))
+                    && !first_line.starts_with('!')
+                    && source.lines().any(|line| line.trim() == first_line);
+
+                if placed {
+                    annotations.push((first_line, label));
+                } else {
+                    unplaced.push((label, ingredient));
+                }
+            }
+        }
+
+        let mut out = String::with_capacity(source.len());
+        for line in source.lines() {
+            // Each annotation is only used once, so repeated lines are attributed in order
+            if let Some(position) = annotations
+                .iter()
+                .position(|(first_line, _)| *first_line == line.trim())
+            {
+                let (_, label) = annotations.remove(position);
+                let indent = &line[..line.len() - line.trim_start().len()];
+                out.push_str(indent);
+                out.push_str(&label);
+                out.push('\n');
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+
+        if !unplaced.is_empty() {
+            out.push_str("\n// Ingredients not present in the source:\n");
+            for (label, ingredient) in unplaced {
+                out.push_str(&label);
+                out.push('\n');
+                for line in ingredient.source.lines() {
+                    out.push_str("//   ");
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                match &ingredient.outcome {
+                    IngredientOutcome::Failure(e) => {
+                        out.push_str("//   failed: ");
+                        out.push_str(&e.replace('\n', " "));
+                        out.push('\n');
+                    }
+                    IngredientOutcome::NotDispatched => {
+                        out.push_str("//   not dispatched, the recipe was rejected\n");
+                    }
+                    IngredientOutcome::Success(_) => {}
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(request: &str, ingredients: &[(&str, IngredientOutcome)]) -> TranscriptEntry {
+        TranscriptEntry {
+            request: request.to_string(),
+            raw_response: String::new(),
+            ingredients: ingredients
+                .iter()
+                .map(|(source, outcome)| IngredientRecord {
+                    source: source.to_string(),
+                    outcome: outcome.clone(),
+                    run_code_offset: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn it_interleaves_requests_with_the_source() {
+        let transcript = Transcript {
+            entries: vec![
+                entry(
+                    "!chat deal me 1 ether",
+                    &[(
+                        "vm.deal(address(this), 1 ether);",
+                        IngredientOutcome::Success(None),
+                    )],
+                ),
+                entry(
+                    "!chat deal me 1 ether again",
+                    &[(
+                        "vm.deal(address(this), 1 ether);",
+                        IngredientOutcome::Success(None),
+                    )],
+                ),
+            ],
+        };
+        let source = "contract REPL {\n    function run() public {\n        vm.deal(address(this), 1 ether);\n        vm.deal(address(this), 1 ether);\n    }\n}";
+
+        assert_eq!(
+            transcript.interleave(source),
+            "contract REPL {
+    function run() public {
+        // [1] !chat deal me 1 ether (ingredient 1)
+        vm.deal(address(this), 1 ether);
+        // [2] !chat deal me 1 ether again (ingredient 1)
+        vm.deal(address(this), 1 ether);
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn it_lists_ingredients_missing_from_the_source_after_it() {
+        let transcript = Transcript {
+            entries: vec![entry(
+                "!chat fork mainnet and read the balance",
+                &[
+                    ("!fork mainnet", IngredientOutcome::Success(None)),
+                    (
+                        "uint256 balance = weth.balanceOf(address(this));",
+                        IngredientOutcome::Failure("Undeclared identifier\nweth".to_string()),
+                    ),
+                    ("balance", IngredientOutcome::NotDispatched),
+                ],
+            )],
+        };
+
+        assert_eq!(
+            transcript.interleave("contract REPL {}"),
+            "contract REPL {}
+
+// Ingredients not present in the source:
+// [1] !chat fork mainnet and read the balance (ingredient 1)
+//   !fork mainnet
+// [1] !chat fork mainnet and read the balance (ingredient 2)
+//   uint256 balance = weth.balanceOf(address(this));
+//   failed: Undeclared identifier weth
+// [1] !chat fork mainnet and read the balance (ingredient 3)
+//   balance
+//   not dispatched, the recipe was rejected
+"
+        );
+    }
+}
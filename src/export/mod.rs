@@ -0,0 +1,173 @@
+//! Exports of the chisel session into files that can be used outside of the REPL
+
+pub mod script;
+pub mod test;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chisel::prelude::{format_source, ChiselDispatcher};
+use eyre::{eyre, Result};
+use foundry_config::FormatterConfig;
+
+use crate::{
+    completion::transcript::{IngredientOutcome, Transcript, TranscriptEntry},
+    helpers::{command_helper::strip_ansi, session::run_code_len},
+};
+
+/// Pragma used when the session source doesn't contain one
+const DEFAULT_PRAGMA: &str = "pragma solidity ^0.8.0;";
+
+/// The value of an expression that was inspected in the REPL
+#[derive(Debug, Clone)]
+pub struct Inspection {
+    /// The inspected expression, without a trailing semicolon
+    pub expression: String,
+    /// The output chisel printed for the expression
+    pub output: String,
+    /// Length of the session's run code when the expression was inspected, used to place the
+    /// derived assertion after the statements it depends on
+    pub run_code_offset: usize,
+}
+
+/// The expressions inspected during a session, in the order they were inspected
+#[derive(Debug, Clone, Default)]
+pub struct Inspections(Vec<Inspection>);
+
+impl Inspections {
+    /// Collects the inspections performed by the recipes of a transcript
+    pub fn from_transcript(transcript: &Transcript, dispatcher: &ChiselDispatcher) -> Self {
+        let mut inspections = Self::default();
+        for entry in &transcript.entries {
+            inspections.record_entry(dispatcher, entry);
+        }
+        inspections
+    }
+
+    /// Records the inspections performed by the ingredients of a recipe, at the offsets recorded
+    /// when they were dispatched
+    pub fn record_entry(&mut self, dispatcher: &ChiselDispatcher, entry: &TranscriptEntry) {
+        for ingredient in &entry.ingredients {
+            if let IngredientOutcome::Success(output) = &ingredient.outcome {
+                let run_code_offset = ingredient
+                    .run_code_offset
+                    .or_else(|| run_code_len(dispatcher))
+                    .unwrap_or_default();
+                self.record_at(&ingredient.source, output.as_deref(), run_code_offset);
+            }
+        }
+    }
+
+    /// Records the output of an input that was just dispatched if it was the inspection of an
+    /// expression
+    pub fn record(&mut self, dispatcher: &ChiselDispatcher, input: &str, output: Option<&str>) {
+        self.record_at(input, output, run_code_len(dispatcher).unwrap_or_default());
+    }
+
+    fn record_at(&mut self, input: &str, output: Option<&str>, run_code_offset: usize) {
+        let Some(output) = output else {
+            return;
+        };
+
+        let input = input.trim();
+        if input.starts_with('!') || !strip_ansi(output).starts_with("Type: ") {
+            return;
+        }
+
+        self.0.push(Inspection {
+            expression: input.trim_end_matches(';').trim().to_string(),
+            output: output.to_string(),
+            run_code_offset,
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Inspection> {
+        self.0.iter()
+    }
+}
+
+/// The pieces of the session source that exports are assembled from
+pub(crate) struct SessionParts {
+    pub pragma: String,
+    pub global_code: String,
+    pub top_level_code: String,
+    pub run_code: String,
+}
+
+impl SessionParts {
+    pub fn new(dispatcher: &ChiselDispatcher) -> Result<Self> {
+        let source = dispatcher
+            .session
+            .session_source
+            .as_ref()
+            .ok_or_else(|| eyre!("The session has no source to export"))?;
+
+        let pragma = source
+            .to_repl_source()
+            .lines()
+            .find(|line| line.trim_start().starts_with("pragma solidity"))
+            .map(|line| line.trim().to_string())
+            .unwrap_or_else(|| DEFAULT_PRAGMA.to_string());
+
+        Ok(Self {
+            pragma,
+            global_code: source.global_code.clone(),
+            top_level_code: source.top_level_code.clone(),
+            run_code: source.run_code.clone(),
+        })
+    }
+}
+
+/// Resolves the path of an export, relative paths are placed inside of `dir` unless they
+/// already start with it.
+pub(crate) fn export_path(path: Option<&str>, dir: &str, default_file: &str) -> PathBuf {
+    let path = PathBuf::from(path.unwrap_or(default_file));
+    if path.is_absolute() || path.starts_with(dir) {
+        path
+    } else {
+        Path::new(dir).join(path)
+    }
+}
+
+/// The contract name for an exported file, derived from its file name, i.e. `Counter.t.sol` is
+/// `Counter`
+pub(crate) fn contract_name(path: &Path) -> String {
+    let name: String = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => name,
+        _ => format!("REPL{name}"),
+    }
+}
+
+/// Formats the exported source and writes it to `path`
+pub(crate) fn write_export(
+    path: &Path,
+    source: &str,
+    formatter_config: FormatterConfig,
+) -> Result<()> {
+    if !Path::new("foundry.toml").exists() {
+        return Err(eyre!(
+            "Must be in a foundry project to export, no foundry.toml found"
+        ));
+    }
+
+    let formatted = format_source(source, formatter_config)
+        .map_err(|e| eyre!("Failed to format the exported source: {e}"))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, formatted)?;
+
+    Ok(())
+}
@@ -0,0 +1,328 @@
+use std::{path::PathBuf, sync::OnceLock};
+
+use chisel::prelude::ChiselDispatcher;
+use eyre::Result;
+use foundry_config::FormatterConfig;
+use regex::{Captures, Regex};
+
+use super::{contract_name, export_path, write_export, SessionParts};
+
+/// Directory scripts are exported into
+const SCRIPT_DIR: &str = "script";
+
+/// File name used when no path is given
+const DEFAULT_SCRIPT_FILE: &str = "REPL.s.sol";
+
+/// Identifiers that suggest a literal next to them is a secret
+const SECRET_NAME_PATTERN: &str =
+    r"(?i)(private|secret|mnemonic|password|passphrase|api_?key|key\b|\bpk\b)";
+
+/// Cheatcodes that take a private key
+const KEY_CHEATCODE_PATTERN: &str = r"vm\.(startBroadcast|broadcast|addr|sign|rememberKey)\s*\(";
+
+/// The result of exporting a script
+#[derive(Debug)]
+pub struct ScriptExport {
+    pub path: PathBuf,
+    /// Environment variables that replaced secrets found in the session, these need to be set
+    /// before running the script
+    pub env_vars: Vec<String>,
+}
+
+/// Exports the session as a forge script.
+///
+/// Contracts declared in the session are hoisted into the file, the `run()` statements are
+/// wrapped in a broadcast and hard-coded addresses are pulled out into named constants. Private
+/// keys, credentialed URLs and other secrets are replaced with environment variable lookups, so
+/// they never end up in the exported file.
+pub fn export_script(
+    dispatcher: &ChiselDispatcher,
+    path: Option<&str>,
+    formatter_config: FormatterConfig,
+) -> Result<ScriptExport> {
+    let path = export_path(path, SCRIPT_DIR, DEFAULT_SCRIPT_FILE);
+    let name = contract_name(&path);
+    let parts = SessionParts::new(dispatcher)?;
+
+    let mut code = [parts.global_code, parts.top_level_code, parts.run_code];
+
+    let mut env_vars = Vec::new();
+    for code in code.iter_mut() {
+        *code = scrub_secrets(code, &mut env_vars);
+    }
+
+    let constants = hoist_addresses(&mut code);
+    let [global_code, top_level_code, run_code] = code;
+
+    let constants = constants
+        .into_iter()
+        .map(|(name, address)| format!("address constant {name} = {address};"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let source = format!(
+        r#"// SPDX-License-Identifier: UNLICENSED
+{pragma}
+
+import {{Script}} from "forge-std/Script.sol";
+
+{constants}
+
+{global_code}
+
+contract {name}Script is Script {{
+{top_level_code}
+
+function run() public {{
+vm.startBroadcast();
+{run_code}
+vm.stopBroadcast();
+}}
+}}
+"#,
+        pragma = parts.pragma,
+    );
+
+    write_export(&path, &source, formatter_config)?;
+
+    Ok(ScriptExport { path, env_vars })
+}
+
+/// Replaces hard-coded addresses with named constants, returns the constants in the order they
+/// were first used.
+///
+/// Names are taken from the surrounding code where possible, i.e. `WETH(0xC02a...)` becomes
+/// `WETH(WETH_ADDRESS)` and `address owner = 0x...` becomes `address owner = OWNER`.
+fn hoist_addresses(code: &mut [String]) -> Vec<(String, String)> {
+    static ADDRESS_RE: OnceLock<Regex> = OnceLock::new();
+    let address_re = ADDRESS_RE.get_or_init(|| Regex::new(r"\b0x[0-9a-fA-F]{40}\b").unwrap());
+    static CAST_RE: OnceLock<Regex> = OnceLock::new();
+    let cast_re = CAST_RE.get_or_init(|| Regex::new(r"([A-Za-z_][A-Za-z0-9_]*)\s*\(\s*$").unwrap());
+    static ASSIGNMENT_RE: OnceLock<Regex> = OnceLock::new();
+    let assignment_re = ASSIGNMENT_RE.get_or_init(|| {
+        Regex::new(r"([A-Za-z_][A-Za-z0-9_]*)\s*=\s*((address|payable)\s*\(\s*)*$").unwrap()
+    });
+    static WORD_RE: OnceLock<Regex> = OnceLock::new();
+    let word_re = WORD_RE.get_or_init(|| Regex::new(r"[A-Za-z_][A-Za-z0-9_]*").unwrap());
+
+    let identifiers: Vec<String> = code
+        .iter()
+        .flat_map(|code| {
+            word_re
+                .find_iter(code)
+                .map(|word| word.as_str().to_string())
+        })
+        .collect();
+
+    let mut constants: Vec<(String, String)> = Vec::new();
+
+    for code in code.iter_mut() {
+        let mut out = String::with_capacity(code.len());
+        let mut last = 0;
+
+        for address in address_re.find_iter(code) {
+            let before = &code[last..address.start()];
+            out.push_str(before);
+            last = address.end();
+
+            // Leave addresses inside of string literals alone
+            if before.ends_with('"') || before.ends_with('\'') {
+                out.push_str(address.as_str());
+                continue;
+            }
+
+            if let Some((name, _)) = constants
+                .iter()
+                .find(|(_, existing)| existing.eq_ignore_ascii_case(address.as_str()))
+            {
+                out.push_str(name);
+                continue;
+            }
+
+            let line = &code[..address.start()];
+            let line = &line[line.rfind('\n').map(|i| i + 1).unwrap_or(0)..];
+
+            let name = match cast_re.captures(line) {
+                Some(cast) if !matches!(&cast[1], "address" | "payable") => {
+                    format!("{}_ADDRESS", screaming_snake_case(&cast[1]))
+                }
+                _ => match assignment_re.captures(line) {
+                    Some(assignment) => screaming_snake_case(&assignment[1]),
+                    None => format!("ADDRESS_{}", constants.len() + 1),
+                },
+            };
+
+            let mut unique = name.clone();
+            let mut suffix = 2;
+            while identifiers.contains(&unique) || constants.iter().any(|(n, _)| *n == unique) {
+                unique = format!("{name}_{suffix}");
+                suffix += 1;
+            }
+
+            out.push_str(&unique);
+            constants.push((unique, address.as_str().to_string()));
+        }
+
+        out.push_str(&code[last..]);
+        *code = out;
+    }
+
+    constants
+}
+
+/// Replaces secrets in the code with environment variable lookups, the names of the variables are
+/// appended to `env_vars`.
+fn scrub_secrets(code: &str, env_vars: &mut Vec<String>) -> String {
+    static SECRET_NAME_RE: OnceLock<Regex> = OnceLock::new();
+    let secret_name_re = SECRET_NAME_RE.get_or_init(|| Regex::new(SECRET_NAME_PATTERN).unwrap());
+    static KEY_CHEATCODE_RE: OnceLock<Regex> = OnceLock::new();
+    let key_cheatcode_re =
+        KEY_CHEATCODE_RE.get_or_init(|| Regex::new(KEY_CHEATCODE_PATTERN).unwrap());
+    static KEY_RE: OnceLock<Regex> = OnceLock::new();
+    let key_re = KEY_RE.get_or_init(|| Regex::new(r"\b0x[0-9a-fA-F]{64}\b").unwrap());
+    static URL_RE: OnceLock<Regex> = OnceLock::new();
+    let url_re = URL_RE.get_or_init(|| Regex::new(r#""(https?://[^"\s]*)""#).unwrap());
+    static STRING_ASSIGNMENT_RE: OnceLock<Regex> = OnceLock::new();
+    let string_assignment_re = STRING_ASSIGNMENT_RE
+        .get_or_init(|| Regex::new(r#"([A-Za-z_][A-Za-z0-9_]*)\s*=\s*"[^"]*""#).unwrap());
+
+    let mut env_var = |base: &str| {
+        let mut name = base.to_string();
+        let mut suffix = 2;
+        while env_vars.contains(&name) {
+            name = format!("{base}_{suffix}");
+            suffix += 1;
+        }
+        env_vars.push(name.clone());
+        name
+    };
+
+    code.lines()
+        .map(|line| {
+            let mut line = line.to_string();
+
+            // 32 byte literals are only keys if they're used like one, hashes and slots look the same
+            if secret_name_re.is_match(&line) || key_cheatcode_re.is_match(&line) {
+                line = key_re
+                    .replace_all(&line, |_: &Captures| {
+                        format!("vm.envUint(\"{}\")", env_var("PRIVATE_KEY"))
+                    })
+                    .into_owned();
+            }
+
+            line = url_re
+                .replace_all(&line, |captures: &Captures| {
+                    if is_credentialed_url(&captures[1]) {
+                        format!("vm.envString(\"{}\")", env_var("RPC_URL"))
+                    } else {
+                        captures[0].to_string()
+                    }
+                })
+                .into_owned();
+
+            string_assignment_re
+                .replace_all(&line, |captures: &Captures| {
+                    let name = &captures[1];
+                    if secret_name_re.is_match(name) {
+                        format!(
+                            "{name} = vm.envString(\"{}\")",
+                            env_var(&screaming_snake_case(name))
+                        )
+                    } else {
+                        captures[0].to_string()
+                    }
+                })
+                .into_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a URL carries credentials, either as user info, a key in the query string or a long
+/// token in the path (the way most RPC providers embed api keys)
+fn is_credentialed_url(url: &str) -> bool {
+    static QUERY_KEY_RE: OnceLock<Regex> = OnceLock::new();
+    let query_key_re = QUERY_KEY_RE
+        .get_or_init(|| Regex::new(r"(?i)[?&](api_?key|key|token|access_token|secret)=").unwrap());
+
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+
+    authority.contains('@')
+        || query_key_re.is_match(path)
+        || path
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_')
+            .any(|segment| segment.len() >= 20)
+}
+
+fn screaming_snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && matches!(previous, Some(p) if p.is_ascii_lowercase()) {
+            out.push('_');
+        }
+        out.push(c.to_ascii_uppercase());
+        previous = Some(c);
+    }
+
+    out.trim_matches('_').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+
+    #[test]
+    fn it_hoists_addresses_into_named_constants() {
+        let mut code = [
+            String::new(),
+            String::new(),
+            format!(
+                "WETH weth = WETH({WETH});\naddress recipient = 0x00000000000000000000000000000000000000aa;\nweth.transfer({WETH}, 1);"
+            ),
+        ];
+
+        let constants = hoist_addresses(&mut code);
+
+        assert_eq!(
+            constants,
+            vec![
+                ("WETH_ADDRESS".to_string(), WETH.to_string()),
+                (
+                    "RECIPIENT".to_string(),
+                    "0x00000000000000000000000000000000000000aa".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            code[2],
+            "WETH weth = WETH(WETH_ADDRESS);\naddress recipient = RECIPIENT;\nweth.transfer(WETH_ADDRESS, 1);"
+        );
+    }
+
+    #[test]
+    fn it_scrubs_private_keys_and_rpc_urls() {
+        let mut env_vars = vec![];
+        let code = "uint256 deployerKey = 0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80;\nvm.createSelectFork(\"https://eth-mainnet.g.alchemy.com/v2/abcdefghijklmnopqrstuvwxyz012345\");\nvm.createSelectFork(\"http://localhost:8545\");";
+
+        assert_eq!(
+            scrub_secrets(code, &mut env_vars),
+            "uint256 deployerKey = vm.envUint(\"PRIVATE_KEY\");\nvm.createSelectFork(vm.envString(\"RPC_URL\"));\nvm.createSelectFork(\"http://localhost:8545\");"
+        );
+        assert_eq!(env_vars, vec!["PRIVATE_KEY", "RPC_URL"]);
+    }
+
+    #[test]
+    fn it_leaves_hashes_alone() {
+        let mut env_vars = vec![];
+        let code =
+            "bytes32 slot = 0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc;";
+
+        assert_eq!(scrub_secrets(code, &mut env_vars), code);
+        assert!(env_vars.is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+use std::path::PathBuf;
+
+use chisel::prelude::ChiselDispatcher;
+use eyre::Result;
+use foundry_config::FormatterConfig;
+
+use super::{contract_name, export_path, write_export, Inspection, Inspections, SessionParts};
+use crate::helpers::command_helper::strip_ansi;
+
+/// Directory tests are exported into
+const TEST_DIR: &str = "test";
+
+/// File name used when no path is given
+const DEFAULT_TEST_FILE: &str = "REPL.t.sol";
+
+/// Exports the session as a forge test, returns the path the test was written to.
+///
+/// The session's contracts are copied as they are, its state variables and functions become
+/// members of the test contract and the `run()` statements become the body of a `test_` function,
+/// with an assertion after the statements for each value that was inspected along the way.
+pub fn export_test(
+    dispatcher: &ChiselDispatcher,
+    inspections: &Inspections,
+    path: Option<&str>,
+    formatter_config: FormatterConfig,
+) -> Result<PathBuf> {
+    let path = export_path(path, TEST_DIR, DEFAULT_TEST_FILE);
+    let name = contract_name(&path);
+    let parts = SessionParts::new(dispatcher)?;
+
+    let source = format!(
+        r#"// SPDX-License-Identifier: UNLICENSED
+{pragma}
+
+import {{Test}} from "forge-std/Test.sol";
+
+{global_code}
+
+contract {name}Test is Test {{
+{top_level_code}
+
+function test_{name}() public {{
+{body}
+}}
+}}
+"#,
+        pragma = parts.pragma,
+        global_code = parts.global_code,
+        top_level_code = parts.top_level_code,
+        body = test_body(&parts.run_code, inspections),
+    );
+
+    write_export(&path, &source, formatter_config)?;
+
+    Ok(path)
+}
+
+/// Interleaves the run code with the assertions derived from the inspections
+fn test_body(run_code: &str, inspections: &Inspections) -> String {
+    let mut body = String::with_capacity(run_code.len());
+    let mut consumed = 0;
+
+    for inspection in inspections.iter() {
+        let Some(assertion) = assertion(inspection) else {
+            continue;
+        };
+
+        // The run code may have been cleared or undone since, so the offset is only a hint
+        let mut offset = inspection.run_code_offset.clamp(consumed, run_code.len());
+        while !run_code.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        body.push_str(&run_code[consumed..offset]);
+        if !body.is_empty() && !body.ends_with('\n') {
+            body.push('\n');
+        }
+        body.push_str(&assertion);
+        body.push('\n');
+        consumed = offset;
+    }
+
+    body.push_str(&run_code[consumed..]);
+    body
+}
+
+/// Derives an assertion from the output chisel printed when inspecting an expression, i.e.
+///
+/// ```text
+/// Type: uint256
+/// ├ Hex: 0x64
+/// └ Decimal: 100
+/// ```
+///
+/// becomes `assertEq(expression, 100);`
+fn assertion(inspection: &Inspection) -> Option<String> {
+    let output = strip_ansi(&inspection.output);
+    let mut lines = output.lines();
+
+    let ty = lines
+        .next()?
+        .trim()
+        .strip_prefix("Type: ")?
+        .trim()
+        .to_string();
+    let fields: Vec<(&str, &str)> = lines
+        .filter_map(|line| {
+            line.trim_start_matches(|c: char| c == '├' || c == '└' || c.is_whitespace())
+                .split_once(": ")
+        })
+        .collect();
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.trim())
+    };
+
+    let expression = &inspection.expression;
+    let expected = match ty.as_str() {
+        "bool" => {
+            return match field("Value")? {
+                "true" => Some(format!("assertTrue({expression});")),
+                "false" => Some(format!("assertFalse({expression});")),
+                _ => None,
+            }
+        }
+        "address" => format!("address({})", field("Data")?),
+        "string" => format!(
+            "\"{}\"",
+            field("UTF-8")?.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        ty if ty.starts_with("uint") || ty.starts_with("int") => field("Decimal")?.to_string(),
+        ty if ty.starts_with("bytes") && ty.len() > "bytes".len() => {
+            format!("{ty}({})", field("Data")?)
+        }
+        _ => return None,
+    };
+
+    Some(format!("assertEq({expression}, {expected});"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chisel::prelude::SessionSource;
+
+    use super::*;
+    use crate::completion::transcript::{
+        IngredientOutcome, IngredientRecord, Transcript, TranscriptEntry,
+    };
+
+    fn inspection(expression: &str, output: &str, run_code_offset: usize) -> Inspection {
+        Inspection {
+            expression: expression.to_string(),
+            output: output.to_string(),
+            run_code_offset,
+        }
+    }
+
+    #[test]
+    fn it_derives_uint_assertions() {
+        let output =
+            "Type: \u{1b}[31muint256\u{1b}[0m\n├ Hex: 0x64\n└ Decimal: \u{1b}[36m100\u{1b}[0m";
+
+        assert_eq!(
+            assertion(&inspection("counter.count()", output, 0)).unwrap(),
+            "assertEq(counter.count(), 100);"
+        );
+    }
+
+    #[test]
+    fn it_derives_bool_and_address_assertions() {
+        assert_eq!(
+            assertion(&inspection("flag", "Type: bool\n└ Value: false", 0)).unwrap(),
+            "assertFalse(flag);"
+        );
+        assert_eq!(
+            assertion(&inspection(
+                "owner",
+                "Type: address\n└ Data: 0x7FA9385bE102ac3EAc297483Dd6233D62b3e1496",
+                0
+            ))
+            .unwrap(),
+            "assertEq(owner, address(0x7FA9385bE102ac3EAc297483Dd6233D62b3e1496));"
+        );
+    }
+
+    #[test]
+    fn it_skips_unsupported_types() {
+        assert!(assertion(&inspection("arr", "Type: dynamic array\n└ Data: []", 0)).is_none());
+    }
+
+    #[test]
+    fn it_places_assertions_after_their_statements() {
+        let run_code = "uint256 a = 1;\na += 1;\n";
+        let mut inspections = Inspections::default();
+        inspections
+            .0
+            .push(inspection("a", "Type: uint256\n└ Decimal: 1", 15));
+        inspections
+            .0
+            .push(inspection("a", "Type: uint256\n└ Decimal: 2", 23));
+
+        assert_eq!(
+            test_body(run_code, &inspections),
+            "uint256 a = 1;\nassertEq(a, 1);\na += 1;\nassertEq(a, 2);\n"
+        );
+    }
+
+    #[test]
+    fn it_places_replayed_inspections_where_they_were_made() {
+        let ingredient =
+            |source: &str, output: Option<&str>, run_code_offset: usize| IngredientRecord {
+                source: source.to_string(),
+                outcome: IngredientOutcome::Success(output.map(str::to_string)),
+                run_code_offset: Some(run_code_offset),
+            };
+        let transcript = Transcript {
+            entries: vec![TranscriptEntry {
+                request: "!chat set x to 1, show it, then set it to 2".to_string(),
+                raw_response: String::new(),
+                ingredients: vec![
+                    ingredient("uint256 x = 1;", None, 15),
+                    ingredient("x", Some("Type: uint256\n└ Decimal: 1"), 15),
+                    ingredient("x = 2;", None, 22),
+                ],
+            }],
+        };
+
+        // The session as it is after the transcript was loaded, `x` has been reassigned since
+        let run_code = "uint256 x = 1;\nx = 2;\n";
+        let mut dispatcher = ChiselDispatcher::default();
+        dispatcher.session.session_source = Some(SessionSource {
+            run_code: run_code.to_string(),
+            ..Default::default()
+        });
+
+        let inspections = Inspections::from_transcript(&transcript, &dispatcher);
+        assert_eq!(
+            test_body(run_code, &inspections),
+            "uint256 x = 1;\nassertEq(x, 1);\nx = 2;\n"
+        );
+    }
+}
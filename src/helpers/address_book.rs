@@ -0,0 +1,342 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use regex::{Captures, Regex};
+use solang_parser::{
+    lexer::{Lexer, Token},
+    pt::{Comment, Loc},
+};
+use tiny_keccak::{Hasher, Keccak};
+
+use super::data_dir::data_dir;
+
+/// Name of the file, inside of the data directory, that holds the labels added with `!label`
+const LABELS_FILE_NAME: &str = "address_book.json";
+
+/// Contracts every session on a chain can refer to, by chain id
+const WELL_KNOWN: &[(u64, &str, &str)] = &[
+    (1, "WETH", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"),
+    (1, "USDC", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+    (1, "USDT", "0xdAC17F958D2ee523a2206206994597C13D831ec7"),
+    (1, "DAI", "0x6B175474E89094C44Da98b954EedeAC495271d0F"),
+    (10, "WETH", "0x4200000000000000000000000000000000000006"),
+    (10, "USDC", "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85"),
+    (137, "WETH", "0x7ceB23fD6bC0adD59E62ac25578270cFf1b9f619"),
+    (137, "USDC", "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359"),
+    (8453, "WETH", "0x4200000000000000000000000000000000000006"),
+    (8453, "USDC", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"),
+    (42161, "WETH", "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1"),
+    (42161, "USDC", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831"),
+];
+
+/// Named addresses the model can refer to as `@name` instead of guessing them. Entries come from
+/// the `[address_book]` table of `chisel_gpt.toml`, from `!label` and, for the chain the session
+/// is forked from, from a list of well-known contracts. Labels win over the configuration, which
+/// wins over well-known contracts.
+#[derive(Debug, Clone, Default)]
+pub struct AddressBook {
+    configured: BTreeMap<String, String>,
+    labels: BTreeMap<String, String>,
+}
+
+impl AddressBook {
+    /// Loads the labels added with `!label` and merges them with the configured entries
+    pub fn load(configured: &BTreeMap<String, String>) -> Result<Self> {
+        let mut book = Self::default();
+        for (name, address) in configured {
+            let (name, address) = validate(name, address)
+                .wrap_err("Invalid entry in the [address_book] of chisel_gpt.toml")?;
+            book.configured.insert(name, address);
+        }
+
+        if let Some(path) = labels_path() {
+            book.labels = load_labels(&path)?;
+        }
+
+        Ok(book)
+    }
+
+    /// Adds or replaces a label and persists it
+    pub fn label(&mut self, name: &str, address: &str) -> Result<()> {
+        let (name, address) = validate(name, address)?;
+        self.labels.insert(name, address);
+
+        let path = labels_path().ok_or_else(|| eyre!("Failed to resolve the foundry directory"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(&self.labels)?)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Every entry for the given chain, by name
+    pub fn entries(&self, chain_id: Option<u64>) -> BTreeMap<String, String> {
+        let mut entries: BTreeMap<String, String> = chain_id
+            .map(well_known)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, address)| (name.to_string(), address.to_string()))
+            .collect();
+        entries.extend(self.configured.clone());
+        entries.extend(self.labels.clone());
+        entries
+    }
+
+    /// The entries a request mentions by name
+    pub fn relevant(&self, request: &str, chain_id: Option<u64>) -> Vec<(String, String)> {
+        let words: Vec<String> = request
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .map(str::to_lowercase)
+            .collect();
+
+        self.entries(chain_id)
+            .into_iter()
+            .filter(|(name, _)| words.contains(&name.to_lowercase()))
+            .collect()
+    }
+
+    /// Replaces `@name` references with the addresses they stand for, returns the rewritten
+    /// ingredient and the entries that were used. Unknown names are left alone, and so are
+    /// comments and string literals, i.e. `/// @notice` or `"a@b"`.
+    pub fn substitute(
+        &self,
+        ingredient: &str,
+        chain_id: Option<u64>,
+    ) -> (String, Vec<(String, String)>) {
+        let entries = self.entries(chain_id);
+        static REFERENCE_RE: OnceLock<Regex> = OnceLock::new();
+        let reference_re =
+            REFERENCE_RE.get_or_init(|| Regex::new(r"@([A-Za-z_][A-Za-z0-9_]*)\b").unwrap());
+
+        let literals = literal_spans(ingredient);
+        let mut used: Vec<(String, String)> = Vec::new();
+        let substituted = reference_re.replace_all(ingredient, |captures: &Captures| {
+            let start = captures.get(0).unwrap().start();
+            if literals.iter().any(|span| span.contains(&start)) {
+                return captures[0].to_string();
+            }
+
+            let name = &captures[1];
+            match entries.get(name) {
+                Some(address) => {
+                    if !used.iter().any(|(used, _)| used == name) {
+                        used.push((name.to_string(), address.clone()));
+                    }
+                    address.clone()
+                }
+                None => captures[0].to_string(),
+            }
+        });
+
+        (substituted.into_owned(), used)
+    }
+}
+
+/// The byte ranges of the comments and string literals in `code`
+fn literal_spans(code: &str) -> Vec<Range<usize>> {
+    let mut comments = Vec::new();
+    let mut errors = Vec::new();
+
+    // `@` isn't Solidity, the lexer reports it and carries on
+    let mut spans: Vec<Range<usize>> = Lexer::new(code, 0, &mut comments, &mut errors)
+        .filter_map(Result::ok)
+        .filter(|(_, token, _)| matches!(token, Token::StringLiteral(..)))
+        .map(|(start, _, end)| start..end)
+        .collect();
+
+    spans.extend(comments.iter().filter_map(|comment| match comment {
+        Comment::Line(loc, _)
+        | Comment::Block(loc, _)
+        | Comment::DocLine(loc, _)
+        | Comment::DocBlock(loc, _) => match loc {
+            Loc::File(_, start, end) => Some(*start..*end),
+            _ => None,
+        },
+    }));
+
+    spans
+}
+
+/// The well-known contracts of a chain, as name and address
+pub fn well_known(chain_id: u64) -> Vec<(&'static str, &'static str)> {
+    WELL_KNOWN
+        .iter()
+        .filter(|(chain, _, _)| *chain == chain_id)
+        .map(|(_, name, address)| (*name, *address))
+        .collect()
+}
+
+/// Reads the labels saved at `path`, there are none if the file doesn't exist. A file that can't be
+/// read is an error, it would be overwritten by the next `!label` otherwise.
+fn load_labels(path: &Path) -> Result<BTreeMap<String, String>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    let contents =
+        fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("Failed to parse the labels in {}", path.display()))
+}
+
+fn labels_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(LABELS_FILE_NAME))
+}
+
+/// Checks that the name can be referenced as `@name` and that the address is one, returns the
+/// address checksummed so that it can be used as a Solidity literal
+fn validate(name: &str, address: &str) -> Result<(String, String)> {
+    static NAME_RE: OnceLock<Regex> = OnceLock::new();
+    static ADDRESS_RE: OnceLock<Regex> = OnceLock::new();
+
+    let name_re = NAME_RE.get_or_init(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+    if !name_re.is_match(name) {
+        return Err(eyre!(
+            "`{name}` is not a valid label, use letters, digits and underscores"
+        ));
+    }
+
+    let address_re = ADDRESS_RE.get_or_init(|| Regex::new(r"^0x[0-9a-fA-F]{40}$").unwrap());
+    if !address_re.is_match(address) {
+        return Err(eyre!("`{address}` is not an address"));
+    }
+
+    Ok((name.to_string(), to_checksum(address)))
+}
+
+/// Checksums an address as described in EIP-55
+fn to_checksum(address: &str) -> String {
+    let address = address.trim_start_matches("0x").to_lowercase();
+
+    let mut hasher = Keccak::v256();
+    hasher.update(address.as_bytes());
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+
+    let checksummed: String = address
+        .chars()
+        .enumerate()
+        .map(|(index, c)| {
+            let nibble = (hash[index / 2] >> if index % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    format!("0x{checksummed}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> AddressBook {
+        AddressBook {
+            configured: BTreeMap::from([(
+                "alice".to_string(),
+                "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string(),
+            )]),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn it_checksums_addresses() {
+        for (_, _, address) in WELL_KNOWN {
+            assert_eq!(&to_checksum(&address.to_lowercase()), address);
+        }
+        assert!(validate("bob", "0x1234").is_err());
+        assert!(validate("not a name", "0x70997970C51812dc3A010C7d01b50e0d17dc79C8").is_err());
+    }
+
+    #[test]
+    fn it_finds_relevant_entries_for_the_chain() {
+        let book = book();
+
+        assert_eq!(
+            book.relevant("!chat wrap 1 ether into weth and send it to Alice", Some(1)),
+            vec![
+                (
+                    "WETH".to_string(),
+                    "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".to_string()
+                ),
+                (
+                    "alice".to_string(),
+                    "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string()
+                ),
+            ]
+        );
+        // Well-known contracts only exist on the chain they were deployed to
+        assert_eq!(book.relevant("!chat use weth", None), vec![]);
+    }
+
+    #[test]
+    fn it_substitutes_references() {
+        let (ingredient, used) =
+            book().substitute("IERC20(@USDC).transfer(@alice, 1e6); // @notice", Some(1));
+
+        assert_eq!(
+            ingredient,
+            "IERC20(0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48).transfer(0x70997970C51812dc3A010C7d01b50e0d17dc79C8, 1e6); // @notice"
+        );
+        assert_eq!(
+            used.into_iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            vec!["USDC", "alice"]
+        );
+    }
+
+    #[test]
+    fn it_leaves_comments_and_strings_alone() {
+        let mut book = book();
+        for name in ["notice", "b"] {
+            book.configured.insert(
+                name.to_string(),
+                "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC".to_string(),
+            );
+        }
+
+        let (ingredient, used) = book.substitute(
+            "/// @notice pays @alice\nstring memory contact = \"a@b\";\nvm.deal(@alice, 1 ether); /* @b */",
+            None,
+        );
+
+        assert_eq!(
+            ingredient,
+            "/// @notice pays @alice\nstring memory contact = \"a@b\";\nvm.deal(0x70997970C51812dc3A010C7d01b50e0d17dc79C8, 1 ether); /* @b */"
+        );
+        assert_eq!(
+            used.into_iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            vec!["alice"]
+        );
+    }
+
+    #[test]
+    fn it_fails_on_unreadable_labels() {
+        let dir = std::env::temp_dir().join(format!("chisel_gpt_labels_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LABELS_FILE_NAME);
+
+        assert!(load_labels(&path).unwrap().is_empty());
+
+        fs::write(
+            &path,
+            r#"{"treasury": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"}"#,
+        )
+        .unwrap();
+        assert_eq!(load_labels(&path).unwrap().len(), 1);
+
+        fs::write(&path, r#"{"treasury": "#).unwrap();
+        assert!(load_labels(&path).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use std::{fs, path::Path, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::completion::foundry_interface::FOUNDRY_INTERFACE;
+
+/// Location of the cheatcode interface inside of a foundry project with forge-std installed
+const VM_SOURCE_PATH: &str = "lib/forge-std/src/Vm.sol";
+
+/// The name and parameters of a function, either a cheatcode or one declared in the session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    /// The parameter list, i.e. `address account, uint256 newBalance`
+    pub params: String,
+}
+
+impl Signature {
+    /// The signature shown to the user, i.e. `deal(address account, uint256 newBalance)`
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params)
+    }
+
+    /// The individual parameters
+    pub fn param_list(&self) -> Vec<&str> {
+        self.params
+            .split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+            .collect()
+    }
+}
+
+/// Loads the cheatcodes from forge-std's `Vm.sol` in the project, falling back to the subset of
+/// the interface that is sent to the model when forge-std isn't installed.
+pub fn load_cheatcodes(root: &Path) -> Vec<Signature> {
+    let source = fs::read_to_string(root.join(VM_SOURCE_PATH))
+        .unwrap_or_else(|_| FOUNDRY_INTERFACE.to_string());
+
+    parse_signatures(&source)
+}
+
+/// Extracts the function declarations from a source, overloads are kept since their parameters
+/// differ
+pub fn parse_signatures(source: &str) -> Vec<Signature> {
+    static FUNCTION_RE: OnceLock<Regex> = OnceLock::new();
+    let function_re = FUNCTION_RE
+        .get_or_init(|| Regex::new(r"function\s+([A-Za-z_][A-Za-z0-9_]*)\s*\(([^)]*)\)").unwrap());
+    static WHITESPACE_RE: OnceLock<Regex> = OnceLock::new();
+    let whitespace_re = WHITESPACE_RE.get_or_init(|| Regex::new(r"\s+").unwrap());
+
+    let mut signatures: Vec<Signature> = Vec::new();
+    for captures in function_re.captures_iter(source) {
+        let signature = Signature {
+            name: captures[1].to_string(),
+            params: whitespace_re
+                .replace_all(captures[2].trim(), " ")
+                .into_owned(),
+        };
+
+        if !signatures.contains(&signature) {
+            signatures.push(signature);
+        }
+    }
+
+    signatures
+}
@@ -0,0 +1,226 @@
+use chisel::prelude::{ChiselDispatcher, SessionSource};
+use eyre::{eyre, Result};
+
+/// The number of automatic checkpoints that are kept, older ones are dropped first
+const MAX_AUTOMATIC: usize = 20;
+
+/// The prefix of the checkpoints created before each recipe, followed by a number that counts up
+/// over the session
+const AUTOMATIC_PREFIX: &str = "chat-";
+
+/// A restore point of the session. Chisel executes the whole session source for every input, so
+/// the source is all it takes to get back to the EVM state of that point: restoring it re-executes
+/// it on the next input. A fork the session was started on without a block number would fork the
+/// latest block again, so it is pinned to the block it was at.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub name: String,
+    /// The request an automatic checkpoint was created for
+    pub request: Option<String>,
+    /// The number of chat transcript entries at the time
+    pub transcript_len: usize,
+    /// The block the session's fork was at, if it is forked
+    pub fork_block_number: Option<u64>,
+    source: SessionSource,
+}
+
+impl Checkpoint {
+    pub fn is_automatic(&self) -> bool {
+        self.request.is_some()
+    }
+}
+
+/// The checkpoints of the session, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoints {
+    checkpoints: Vec<Checkpoint>,
+    /// The number of automatic checkpoints created so far, numbers aren't reused after a restore
+    automatic_count: usize,
+}
+
+impl Checkpoints {
+    /// Captures the session under `name`, replacing an earlier checkpoint of the same name
+    pub fn create(
+        &mut self,
+        dispatcher: &ChiselDispatcher,
+        name: &str,
+        transcript_len: usize,
+        fork_block_number: Option<u64>,
+    ) -> Result<&Checkpoint> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(eyre!("`{name}` is not a valid checkpoint name"));
+        }
+        if name.starts_with(AUTOMATIC_PREFIX) {
+            return Err(eyre!(
+                "Checkpoint names starting with `{AUTOMATIC_PREFIX}` are reserved for the checkpoints created before each recipe"
+            ));
+        }
+
+        self.insert(
+            dispatcher,
+            name.to_string(),
+            None,
+            transcript_len,
+            fork_block_number,
+        )
+    }
+
+    /// Captures the session before the recipe for `request` is dispatched
+    pub fn create_automatic(
+        &mut self,
+        dispatcher: &ChiselDispatcher,
+        request: &str,
+        transcript_len: usize,
+        fork_block_number: Option<u64>,
+    ) -> Result<&Checkpoint> {
+        self.automatic_count += 1;
+        let name = format!("{AUTOMATIC_PREFIX}{}", self.automatic_count);
+        self.insert(
+            dispatcher,
+            name,
+            Some(request),
+            transcript_len,
+            fork_block_number,
+        )
+    }
+
+    fn insert(
+        &mut self,
+        dispatcher: &ChiselDispatcher,
+        name: String,
+        request: Option<&str>,
+        transcript_len: usize,
+        fork_block_number: Option<u64>,
+    ) -> Result<&Checkpoint> {
+        let source = dispatcher
+            .session
+            .session_source
+            .clone()
+            .ok_or_else(|| eyre!("The session has no source to checkpoint"))?;
+
+        self.checkpoints
+            .retain(|checkpoint| checkpoint.name != name);
+        self.checkpoints.push(Checkpoint {
+            name,
+            request: request.map(str::to_string),
+            transcript_len,
+            fork_block_number,
+            source,
+        });
+
+        let automatic = self
+            .checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.is_automatic())
+            .count();
+        if automatic > MAX_AUTOMATIC {
+            let oldest = self.checkpoints.iter().position(Checkpoint::is_automatic);
+            if let Some(oldest) = oldest {
+                self.checkpoints.remove(oldest);
+            }
+        }
+
+        Ok(self.checkpoints.last().unwrap())
+    }
+
+    /// Puts the source of the checkpoint named `name` back into the session, on the fork block
+    /// it was created at
+    pub fn restore(&self, dispatcher: &mut ChiselDispatcher, name: &str) -> Result<&Checkpoint> {
+        let checkpoint = self
+            .checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.name == name)
+            .ok_or_else(|| eyre!("No checkpoint named `{name}`, list them with !checkpoints"))?;
+
+        let mut source = checkpoint.source.clone();
+        let evm_opts = &mut source.config.evm_opts;
+        if evm_opts.fork_url.is_some() && evm_opts.fork_block_number.is_none() {
+            if let Some(block) = checkpoint.fork_block_number {
+                evm_opts.fork_block_number = Some(block);
+                // Chisel forks again from the options once it has no backend
+                source.config.backend = None;
+            }
+        }
+
+        dispatcher.session.session_source = Some(source);
+        Ok(checkpoint)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.checkpoints.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dispatcher(run_code: &str) -> ChiselDispatcher {
+        let mut dispatcher = ChiselDispatcher::default();
+        dispatcher.session.session_source = Some(SessionSource {
+            run_code: run_code.to_string(),
+            ..Default::default()
+        });
+        dispatcher
+    }
+
+    #[test]
+    fn it_restores_the_source_of_a_checkpoint() {
+        let mut checkpoints = Checkpoints::default();
+        checkpoints
+            .create(&dispatcher("uint256 x = 1;"), "before", 0, None)
+            .unwrap();
+
+        let mut later = dispatcher("uint256 x = 1;\nx = 2;");
+        let restored = checkpoints.restore(&mut later, "before").unwrap();
+
+        assert_eq!(restored.transcript_len, 0);
+        assert_eq!(
+            later.session.session_source.unwrap().run_code,
+            "uint256 x = 1;"
+        );
+        assert!(checkpoints.restore(&mut dispatcher(""), "after").is_err());
+        assert!(checkpoints
+            .create(&dispatcher(""), "two words", 0, None)
+            .is_err());
+        assert!(checkpoints
+            .create(&dispatcher(""), "chat-1", 0, None)
+            .is_err());
+    }
+
+    #[test]
+    fn it_restores_forks_at_the_block_they_were_at() {
+        let mut checkpoints = Checkpoints::default();
+        let mut forked = dispatcher("uint256 x = 1;");
+        let source = forked.session.session_source.as_mut().unwrap();
+        source.config.evm_opts.fork_url = Some("https://eth.llamarpc.com".to_string());
+        source.config.backend = Some(Default::default());
+
+        checkpoints
+            .create(&forked, "before", 0, Some(18_000_000))
+            .unwrap();
+        checkpoints.restore(&mut forked, "before").unwrap();
+
+        let config = forked.session.session_source.unwrap().config;
+        assert_eq!(config.evm_opts.fork_block_number, Some(18_000_000));
+        assert!(config.backend.is_none());
+    }
+
+    #[test]
+    fn it_keeps_a_limited_number_of_automatic_checkpoints() {
+        let mut checkpoints = Checkpoints::default();
+        let dispatcher = dispatcher("");
+
+        checkpoints.create(&dispatcher, "start", 0, None).unwrap();
+        for number in 0..MAX_AUTOMATIC + 2 {
+            checkpoints
+                .create_automatic(&dispatcher, "!chat deal me 1 ether", number, None)
+                .unwrap();
+        }
+
+        let names: Vec<&str> = checkpoints.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names.len(), MAX_AUTOMATIC + 1);
+        assert_eq!(names[0], "start");
+        assert_eq!(names[1], "chat-3");
+    }
+}
@@ -1,21 +1,26 @@
 // ! Had to copy most of solidity_helper from foundry to be able to modify the highlight method so that it returns a green color for commands prefixed with '!chat'. Rust makes it very difficult to modify struct's outside of scope, tried dynamic dispatching but that didn't work
 
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::OnceLock};
 
 use chisel::{
     prelude::{COMMAND_LEADER, PROMPT_ARROW},
     solidity_helper::SolidityHelper,
 };
+use regex::Regex;
 use rustyline::{
     completion::Completer,
     highlight::Highlighter,
     hint::Hinter,
+    history::SearchDirection,
     validate::{ValidationContext, ValidationResult, Validator},
-    Helper,
+    Context, Helper,
 };
 use solang_parser::lexer::{Lexer, LexicalError, Token};
 use yansi::{Color, Paint, Style};
 
+use super::{cheatcodes::Signature, prose::looks_like_prose};
+use crate::commands::COMMANDS;
+
 /// The default pre-allocation for solang parsed comments
 const DEFAULT_COMMENTS: usize = 5;
 
@@ -28,11 +33,45 @@ const DEFAULT_COMMENTS: usize = 5;
 /// * 4 - suffix: `\x1B[0m`
 const MAX_ANSI_LEN: usize = 9;
 
+/// Inline code spans, addresses and amounts with a unit, picked out of the text of a request
+const REQUEST_SPAN_PATTERN: &str = r"`[^`]*`?|\b0x[0-9a-fA-F]{40}\b|\b\d[\d_]*(?:\.\d+)?(?:e\d+)?\s*(?:wei|gwei|ether|seconds|minutes|hours|days|weeks)\b";
+
+/// The name of a command and the shape of its arguments, i.e. `fetch` and `<addr> <name>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandSyntax {
+    pub name: String,
+    pub args: String,
+}
+
+/// Removes ANSI colour codes, i.e. from chisel's output
+pub fn strip_ansi(input: &str) -> String {
+    static ANSI_RE: OnceLock<Regex> = OnceLock::new();
+
+    ANSI_RE
+        .get_or_init(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap())
+        .replace_all(input, "")
+        .into_owned()
+}
+
 /// A rustyline helper for Solidity code
 #[derive(Clone, Debug, Default)]
 pub struct CommandHelper {
     /// Whether the dispatcher has errored.
     pub errored: bool,
+    /// The chisel commands, without the command leader
+    commands: Vec<CommandSyntax>,
+    /// Names declared in the session source
+    session_names: Vec<String>,
+    /// Functions declared in the session source
+    session_functions: Vec<Signature>,
+    /// Cheatcodes available through `vm`
+    cheatcodes: Vec<Signature>,
+    /// Addresses that have been fetched with `!fetch`
+    fetched_addresses: Vec<String>,
+    /// Whether input that reads like prose is accepted as is, see [looks_like_prose]
+    auto: bool,
+    /// Name of the active model profile
+    profile: Option<String>,
 }
 
 impl CommandHelper {
@@ -47,6 +86,260 @@ impl CommandHelper {
         self
     }
 
+    /// Set whether input that reads like prose is accepted, rather than validated as Solidity.
+    pub fn set_auto(&mut self, auto: bool) -> &mut Self {
+        self.auto = auto;
+        self
+    }
+
+    /// Set the name of the active model profile, which is shown in the prompt.
+    pub fn set_profile(&mut self, profile: &str) -> &mut Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    /// Adds the active model profile to chisel's prompt, i.e. `(ID: 1) ➜ ` becomes
+    /// `(ID: 1) [strong] ➜ `
+    pub fn prompt(&self, prompt: &str) -> String {
+        match (&self.profile, prompt.find(PROMPT_ARROW)) {
+            (Some(profile), Some(i)) => format!("{}[{profile}] {}", &prompt[..i], &prompt[i..]),
+            _ => prompt.to_string(),
+        }
+    }
+
+    /// Set the chisel commands that can be completed.
+    pub fn set_commands(&mut self, commands: Vec<CommandSyntax>) -> &mut Self {
+        self.commands = commands;
+        self
+    }
+
+    /// Set the names declared in the session source.
+    pub fn set_session_names(&mut self, session_names: Vec<String>) -> &mut Self {
+        self.session_names = session_names;
+        self
+    }
+
+    /// Set the functions declared in the session source.
+    pub fn set_session_functions(&mut self, session_functions: Vec<Signature>) -> &mut Self {
+        self.session_functions = session_functions;
+        self
+    }
+
+    /// Set the cheatcodes that can be completed after `vm.`.
+    pub fn set_cheatcodes(&mut self, cheatcodes: Vec<Signature>) -> &mut Self {
+        self.cheatcodes = cheatcodes;
+        self
+    }
+
+    /// Remember an address passed to `!fetch`.
+    pub fn add_fetched_address(&mut self, address: &str) -> &mut Self {
+        if !self.fetched_addresses.iter().any(|a| a == address) {
+            self.fetched_addresses.push(address.to_string());
+        }
+        self
+    }
+
+    /// Parses the chisel commands out of the `!help` output, which lists them as
+    /// `!name <args> | !alias <args> - description`
+    pub fn parse_commands(help_text: &str) -> Vec<CommandSyntax> {
+        let mut commands: Vec<CommandSyntax> = Vec::new();
+
+        for line in strip_ansi(help_text).lines() {
+            let syntax = line.split(" - ").next().unwrap_or_default();
+
+            for part in syntax.split(" | ") {
+                let Some(part) = part.trim().strip_prefix(COMMAND_LEADER) else {
+                    continue;
+                };
+
+                let (name, args) = part.split_once(' ').unwrap_or((part, ""));
+                if !name.is_empty() && !commands.iter().any(|c| c.name == name) {
+                    commands.push(CommandSyntax {
+                        name: name.to_string(),
+                        args: args.trim().to_string(),
+                    });
+                }
+            }
+        }
+
+        commands
+    }
+
+    /// All commands, chisel's followed by ChiselGPT's
+    fn all_commands(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.commands
+            .iter()
+            .map(|command| (command.name.as_str(), command.args.as_str()))
+            .chain(
+                COMMANDS.iter().flat_map(|descriptor| {
+                    descriptor.names().map(move |name| (name, descriptor.args))
+                }),
+            )
+    }
+
+    /// Returns the start of the text being completed and the candidates for it
+    fn candidates(&self, input: &str) -> (usize, Vec<String>) {
+        if let Some(command) = input.strip_prefix(COMMAND_LEADER) {
+            match command.split_once(' ') {
+                // Command names
+                None => {
+                    let names = self.all_commands().map(|(name, _)| name);
+                    return (0, Self::matching(names, command, |name| format!("!{name}")));
+                }
+                // Addresses after `!fetch`
+                Some(("fe" | "fetch", address)) if !address.contains(' ') => {
+                    let start = input.len() - address.len();
+                    let addresses = self.fetched_addresses.iter().map(String::as_str);
+                    return (start, Self::matching(addresses, address, str::to_string));
+                }
+                _ => {}
+            }
+        }
+
+        let word_len: usize = input
+            .chars()
+            .rev()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+            .map(char::len_utf8)
+            .sum();
+        let word_start = input.len() - word_len;
+        let word = &input[word_start..];
+
+        // Cheatcodes after `vm.`
+        if let Some(prefix) = word.strip_prefix("vm.") {
+            let names = self
+                .cheatcodes
+                .iter()
+                .map(|cheatcode| cheatcode.name.as_str());
+            return (
+                word_start + 3,
+                Self::matching(names, prefix, str::to_string),
+            );
+        }
+
+        if word.is_empty() || word.contains('.') {
+            return (input.len(), vec![]);
+        }
+
+        // Contracts, functions and variables declared in the session
+        let names = self.session_names.iter().map(String::as_str);
+        (word_start, Self::matching(names, word, str::to_string))
+    }
+
+    /// Returns the hint shown after the cursor at the end of the input
+    fn hint_for(&self, input: &str, history: impl Fn(&str) -> Option<String>) -> Option<String> {
+        if let Some(command) = input.strip_prefix(COMMAND_LEADER) {
+            // Recent chat requests
+            if command.starts_with("chat") && command.len() > "chat ".len() {
+                return history(input).and_then(|entry| entry.get(input.len()..).map(String::from));
+            }
+
+            // The arguments of the command, until the first one is typed
+            let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+            if !rest.trim().is_empty() {
+                return None;
+            }
+
+            let (_, args) = self.all_commands().find(|(n, _)| *n == name)?;
+            return match (args.is_empty(), command.ends_with(' ')) {
+                (true, _) => None,
+                (false, true) => Some(args.to_string()),
+                (false, false) => Some(format!(" {args}")),
+            };
+        }
+
+        self.call_hint(input).or_else(|| self.name_hint(input))
+    }
+
+    /// The remaining parameters of the innermost unclosed call, shown when the cursor is at the
+    /// start of an argument, i.e. `vm.deal(alice, ` hints `uint256 newBalance)`
+    fn call_hint(&self, input: &str) -> Option<String> {
+        let mut open_calls = Vec::new();
+        for (index, c) in input.char_indices() {
+            match c {
+                '(' => open_calls.push(index),
+                ')' => {
+                    open_calls.pop();
+                }
+                _ => {}
+            }
+        }
+        let open = *open_calls.last()?;
+
+        let callee = trailing_identifier(input[..open].trim_end());
+        let signature = match callee.strip_prefix("vm.") {
+            Some(name) => self.cheatcodes.iter().find(|s| s.name == name),
+            None => {
+                let name = callee.rsplit('.').next()?;
+                self.session_functions.iter().find(|s| s.name == name)
+            }
+        }?;
+
+        // Only hint between arguments, rather than over one that is being typed
+        let args = &input[open + 1..];
+        let mut depth = 0usize;
+        let mut arg_index = 0;
+        let mut current_arg_start = 0;
+        for (index, c) in args.char_indices() {
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    arg_index += 1;
+                    current_arg_start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        if !args[current_arg_start..].trim().is_empty() {
+            return None;
+        }
+
+        let params = signature.param_list();
+        if arg_index > params.len() {
+            return None;
+        }
+
+        Some(format!("{})", params[arg_index..].join(", ")))
+    }
+
+    /// Completes a partially typed cheatcode or session function when there is a single match,
+    /// i.e. `vm.dea` hints `l(address account, uint256 newBalance)`
+    fn name_hint(&self, input: &str) -> Option<String> {
+        let word = trailing_identifier(input);
+
+        let (prefix, signatures) = match word.strip_prefix("vm.") {
+            Some(prefix) => (prefix, &self.cheatcodes),
+            None => (word.rsplit('.').next()?, &self.session_functions),
+        };
+        if prefix.is_empty() {
+            return None;
+        }
+
+        let mut matches = signatures.iter().filter(|s| s.name.starts_with(prefix));
+        let signature = matches.next()?;
+        if matches.any(|s| s.name != signature.name) {
+            return None;
+        }
+
+        Some(signature.signature()[prefix.len()..].to_string())
+    }
+
+    /// The sorted, deduplicated names starting with `prefix`
+    fn matching<'a>(
+        names: impl Iterator<Item = &'a str>,
+        prefix: &str,
+        candidate: impl Fn(&str) -> String,
+    ) -> Vec<String> {
+        let mut candidates: Vec<String> = names
+            .filter(|name| name.starts_with(prefix))
+            .map(candidate)
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
     /// Highlights a solidity source string
     pub fn highlight(input: &str) -> Cow<str> {
         if input.starts_with("!chat") {
@@ -76,7 +369,7 @@ impl CommandHelper {
             match rest {
                 Some(rest) if !rest.is_empty() => {
                     out.push(' ');
-                    out.push_str(rest);
+                    Self::highlight_request(rest, &mut out);
                 }
                 _ => {}
             }
@@ -87,8 +380,55 @@ impl CommandHelper {
         }
     }
 
+    /// Highlights the text of a request, so that inline code spans (highlighted as Solidity),
+    /// addresses and amounts stand out from the natural-language part around them
+    fn highlight_request(request: &str, out: &mut String) {
+        if !Paint::is_enabled() {
+            out.push_str(request);
+            return;
+        }
+
+        static SPAN_RE: OnceLock<Regex> = OnceLock::new();
+        let span_re = SPAN_RE.get_or_init(|| Regex::new(REQUEST_SPAN_PATTERN).unwrap());
+        let prose = Style::default().italic();
+        let backtick = Style::default().dimmed();
+
+        let paint_prose = |text: &str, out: &mut String| {
+            if !text.is_empty() {
+                Self::paint_unchecked(text, prose, out);
+            }
+        };
+
+        let mut last = 0;
+        for span in span_re.find_iter(request) {
+            paint_prose(&request[last..span.start()], out);
+            last = span.end();
+
+            let text = span.as_str();
+            if let Some(code) = text.strip_prefix('`') {
+                // The closing backtick may not have been typed yet
+                let (code, closed) = match code.strip_suffix('`') {
+                    Some(code) => (code, true),
+                    None => (code, false),
+                };
+
+                Self::paint_unchecked("`", backtick, out);
+                out.push_str(&SolidityHelper::highlight(code));
+                if closed {
+                    Self::paint_unchecked("`", backtick, out);
+                }
+            } else if text.starts_with("0x") {
+                Self::paint_unchecked(text, Color::Yellow.style(), out);
+            } else {
+                Self::paint_unchecked(text, Color::Cyan.style(), out);
+            }
+        }
+
+        paint_prose(&request[last..], out);
+    }
+
     /// Validate that a source snippet is closed (i.e., all braces and parenthesis are matched).
-    fn validate_closed(input: &str) -> ValidationResult {
+    pub fn validate_closed(input: &str) -> ValidationResult {
         if Self::skip_input(input) {
             let msg = Paint::red("\nInput must not start with `.<number>`");
             return ValidationResult::Invalid(Some(msg.to_string()));
@@ -172,6 +512,14 @@ impl Highlighter for CommandHelper {
         Self::highlight(line)
     }
 
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if !Paint::is_enabled() {
+            return Cow::Borrowed(hint);
+        }
+
+        Cow::Owned(Paint::new(hint).dimmed().to_string())
+    }
+
     fn highlight_char(&self, line: &str, pos: usize) -> bool {
         pos == line.len()
     }
@@ -202,6 +550,16 @@ impl Highlighter for CommandHelper {
             );
         }
 
+        if let Some(profile) = &self.profile {
+            if let Some(i) = out.rfind(&format!("[{profile}]")) {
+                let profile_span = i + 1..i + 1 + profile.len();
+                out.replace_range(
+                    profile_span,
+                    &Self::paint_unchecked_owned(profile, Color::Magenta.style()),
+                );
+            }
+        }
+
         if let Some(i) = out.find(PROMPT_ARROW) {
             let style = if self.errored {
                 Color::Red.style()
@@ -224,16 +582,197 @@ impl Highlighter for CommandHelper {
 
 impl Validator for CommandHelper {
     fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        // Apostrophes in a request would otherwise wait for the string to be closed
+        if self.auto && looks_like_prose(ctx.input()) {
+            return Ok(ValidationResult::Valid(None));
+        }
+
         Ok(Self::validate_closed(ctx.input()))
     }
 }
 
 impl Completer for CommandHelper {
     type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        Ok(self.candidates(&line[..pos]))
+    }
 }
 
 impl Hinter for CommandHelper {
     type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<Self::Hint> {
+        if line.is_empty() || pos < line.len() {
+            return None;
+        }
+
+        // The most recent history entry starting with the input
+        let history = |input: &str| {
+            let history = ctx.history();
+            if history.is_empty() {
+                return None;
+            }
+
+            let start = ctx.history_index().min(history.len() - 1);
+            history
+                .starts_with(input, start, SearchDirection::Reverse)
+                .ok()
+                .flatten()
+                .map(|result| result.entry.into_owned())
+                .filter(|entry| entry != input)
+        };
+
+        self.hint_for(line, history)
+    }
+}
+
+/// The identifier, including member accesses, at the end of the input, i.e. `vm.deal` in
+/// `uint256 x = vm.deal`
+fn trailing_identifier(input: &str) -> &str {
+    let len: usize = input
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+        .map(char::len_utf8)
+        .sum();
+
+    &input[input.len() - len..]
 }
 
 impl Helper for CommandHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn helper() -> CommandHelper {
+        let mut helper = CommandHelper::new();
+        helper
+            .set_commands(CommandHelper::parse_commands(
+                "!fetch <addr> <name> - Fetch an interface\n!fork <url> - Fork",
+            ))
+            .set_session_names(vec!["weth".to_string(), "withdraw".to_string()])
+            .set_session_functions(vec![Signature {
+                name: "withdraw".to_string(),
+                params: "uint256 amount".to_string(),
+            }])
+            .set_cheatcodes(vec![Signature {
+                name: "deal".to_string(),
+                params: "address account, uint256 newBalance".to_string(),
+            }])
+            .add_fetched_address("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+        helper
+    }
+
+    #[test]
+    fn it_completes_commands() {
+        assert_eq!(
+            helper().candidates("!f"),
+            (
+                0,
+                vec![
+                    "!fetch".to_string(),
+                    "!fix".to_string(),
+                    "!fork".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            helper().candidates("!ch").1,
+            vec!["!chat", "!chat!", "!checkpoint", "!checkpoints"]
+        );
+    }
+
+    #[test]
+    fn it_completes_in_context() {
+        assert_eq!(
+            helper().candidates("!fe 0xC0"),
+            (
+                4,
+                vec!["0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".to_string()]
+            )
+        );
+        assert_eq!(helper().candidates("vm.de"), (3, vec!["deal".to_string()]));
+        assert_eq!(
+            helper().candidates("weth.deposit(); w"),
+            (16, vec!["weth".to_string(), "withdraw".to_string()])
+        );
+    }
+
+    #[test]
+    fn it_parses_commands_from_help() {
+        let help =
+            "\u{1b}[32m!help | !h - Display all commands\n!fetch <addr> <name> - Fetch\nx != y";
+        let commands = CommandHelper::parse_commands(help);
+
+        assert_eq!(
+            commands.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            vec!["help", "h", "fetch"]
+        );
+        assert_eq!(commands[2].args, "<addr> <name>");
+    }
+
+    #[test]
+    fn it_highlights_request_spans() {
+        let input = "!chat send 1.5 ether to 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2 with `weth";
+        let highlighted = CommandHelper::highlight(input);
+
+        // Highlighting must not change what is displayed
+        assert_eq!(strip_ansi(&highlighted), input);
+        assert!(highlighted.contains(&Paint::cyan("1.5 ether").to_string()));
+        assert!(highlighted
+            .contains(&Paint::yellow("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").to_string()));
+    }
+
+    #[test]
+    fn it_hints_call_parameters() {
+        let no_history = |_: &str| None;
+
+        assert_eq!(
+            helper().hint_for("vm.deal(", no_history).unwrap(),
+            "address account, uint256 newBalance)"
+        );
+        assert_eq!(
+            helper().hint_for("vm.deal(alice, ", no_history).unwrap(),
+            "uint256 newBalance)"
+        );
+        assert!(helper().hint_for("vm.deal(ali", no_history).is_none());
+        assert_eq!(
+            helper().hint_for("weth.withdraw(", no_history).unwrap(),
+            "uint256 amount)"
+        );
+        assert_eq!(
+            helper().hint_for("vm.de", no_history).unwrap(),
+            "al(address account, uint256 newBalance)"
+        );
+    }
+
+    #[test]
+    fn it_hints_command_arguments_and_chat_history() {
+        let history = |input: &str| {
+            "!chat wrap 1 ether of weth"
+                .starts_with(input)
+                .then(|| "!chat wrap 1 ether of weth".to_string())
+        };
+
+        assert_eq!(
+            helper().hint_for("!fetch", history).unwrap(),
+            " <addr> <name>"
+        );
+        assert_eq!(
+            helper().hint_for("!export-test ", history).unwrap(),
+            "[path]"
+        );
+        assert!(helper().hint_for("!fetch 0x", history).is_none());
+        assert_eq!(
+            helper().hint_for("!chat wrap", history).unwrap(),
+            " 1 ether of weth"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::{fmt, ops::Range, path::Path};
+
+use chisel::prelude::SessionSource;
+use eyre::Result;
+
+use super::recipe::IngredientKind;
+
+/// A compiler error, or parser error, in a recipe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipeDiagnostic {
+    /// The number of the ingredient the error points at, `None` if it points outside the recipe
+    pub ingredient: Option<usize>,
+    /// The line within the ingredient, starting at 1
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for RecipeDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.ingredient, self.line) {
+            (Some(ingredient), Some(line)) => {
+                write!(f, "Ingredient {ingredient}, line {line}: {}", self.message)
+            }
+            (Some(ingredient), None) => write!(f, "Ingredient {ingredient}: {}", self.message),
+            (None, _) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The outcome of compiling a recipe against a copy of the session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecipeCheck {
+    /// Every ingredient compiled
+    Compiled,
+    /// The ingredients before this one compiled. It is a chisel command, which can't be replayed
+    /// on a copy of the session, so it and the ingredients after it are only checked as they are
+    /// dispatched.
+    CompiledUntilCommand(usize),
+    Failed(Vec<RecipeDiagnostic>),
+}
+
+/// Where the code of an ingredient ended up in the session source
+#[derive(Debug, Clone)]
+struct IngredientSpan {
+    number: usize,
+    /// Index into [sections]
+    section: usize,
+    range: Range<usize>,
+}
+
+/// The parts of the session source ingredients are appended to, in the order they appear in the
+/// generated contract
+fn sections(source: &SessionSource) -> [&str; 3] {
+    [
+        &source.global_code,
+        &source.top_level_code,
+        &source.run_code,
+    ]
+}
+
+/// Adds the ingredients to a throwaway copy of the session and compiles it, so that a recipe that
+/// doesn't compile never touches the real session. Errors are only returned if the compiler
+/// couldn't be run at all.
+pub fn check_recipe(source: &SessionSource, ingredients: &[String]) -> Result<RecipeCheck> {
+    let checked = ingredients
+        .iter()
+        .take_while(|ingredient| IngredientKind::of(ingredient) != IngredientKind::Command)
+        .count();
+    if checked == 0 {
+        return Ok(if ingredients.is_empty() {
+            RecipeCheck::Compiled
+        } else {
+            RecipeCheck::CompiledUntilCommand(1)
+        });
+    }
+
+    let mut copy = source.clone();
+    let mut spans = Vec::with_capacity(checked);
+
+    for (index, ingredient) in ingredients[..checked].iter().enumerate() {
+        let number = index + 1;
+        let before = sections(&copy).map(str::len);
+        let (next, _) = match copy.clone_with_new_line(ingredient.clone()) {
+            Ok(next) => next,
+            Err(e) => {
+                return Ok(RecipeCheck::Failed(vec![RecipeDiagnostic {
+                    ingredient: Some(number),
+                    line: None,
+                    message: e.to_string().trim().to_string(),
+                }]))
+            }
+        };
+        let after = sections(&next).map(str::len);
+
+        if let Some(section) = (0..3).find(|&section| before[section] != after[section]) {
+            spans.push(IngredientSpan {
+                number,
+                section,
+                range: before[section]..after[section],
+            });
+        }
+        copy = next;
+    }
+
+    let output = copy.compile()?;
+    let repl_source = copy.to_repl_source();
+
+    let diagnostics: Vec<RecipeDiagnostic> = output
+        .errors
+        .iter()
+        .filter(|error| error.severity.is_error())
+        .map(|error| {
+            let location = error
+                .source_location
+                .as_ref()
+                .filter(|location| Path::new(&location.file) == copy.file_name)
+                .and_then(|location| {
+                    locate(
+                        &repl_source,
+                        sections(&copy),
+                        &spans,
+                        location.start as usize,
+                    )
+                });
+
+            RecipeDiagnostic {
+                ingredient: location.map(|(number, _)| number),
+                line: location.map(|(_, line)| line),
+                message: format!("{}: {}", error.r#type, error.message),
+            }
+        })
+        .collect();
+
+    Ok(if !diagnostics.is_empty() {
+        RecipeCheck::Failed(diagnostics)
+    } else if checked < ingredients.len() {
+        RecipeCheck::CompiledUntilCommand(checked + 1)
+    } else {
+        RecipeCheck::Compiled
+    })
+}
+
+/// Finds the ingredient, and the line within it, that a byte offset into the generated source
+/// points at
+fn locate(
+    repl_source: &str,
+    sections: [&str; 3],
+    spans: &[IngredientSpan],
+    offset: usize,
+) -> Option<(usize, usize)> {
+    // The sections appear in order, so each one is searched for after the previous one
+    let mut starts = [0; 3];
+    let mut position = 0;
+    for (index, section) in sections.iter().enumerate() {
+        position += repl_source.get(position..)?.find(section)?;
+        starts[index] = position;
+        position += section.len();
+    }
+
+    spans.iter().find_map(|span| {
+        let start = starts[span.section] + span.range.start;
+        let end = starts[span.section] + span.range.end;
+        (start..end).contains(&offset).then(|| {
+            let line = sections[span.section][span.range.start..offset - starts[span.section]]
+                .matches('\n')
+                .count();
+            (span.number, line + 1)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_offsets_to_ingredients() {
+        let global = "function double(uint256 x) pure returns (uint256) {\nreturn x * 2;\n}\n";
+        let top_level = "";
+        let run = "uint256 a = 1;\nuint256 b = double(a);\n";
+        let repl_source = format!(
+            "pragma solidity ^0.8.19;\n\n{global}\ncontract REPL {{\n{top_level}\nfunction run() public {{\n{run}}}\n}}\n"
+        );
+
+        let spans = vec![
+            IngredientSpan {
+                number: 1,
+                section: 0,
+                range: 0..global.len(),
+            },
+            IngredientSpan {
+                number: 2,
+                section: 2,
+                range: 0..15,
+            },
+            IngredientSpan {
+                number: 3,
+                section: 2,
+                range: 15..run.len(),
+            },
+        ];
+        let sections = [global, top_level, run];
+
+        let offset_of = |needle: &str| repl_source.find(needle).unwrap();
+        assert_eq!(
+            locate(&repl_source, sections, &spans, offset_of("x * 2")),
+            Some((1, 2))
+        );
+        assert_eq!(
+            locate(&repl_source, sections, &spans, offset_of("double(a)")),
+            Some((3, 1))
+        );
+        assert_eq!(
+            locate(&repl_source, sections, &spans, offset_of("pragma")),
+            None
+        );
+    }
+
+    #[test]
+    fn it_formats_diagnostics() {
+        let diagnostic = RecipeDiagnostic {
+            ingredient: Some(3),
+            line: Some(2),
+            message: "DeclarationError: Undeclared identifier.".to_string(),
+        };
+        assert_eq!(
+            diagnostic.to_string(),
+            "Ingredient 3, line 2: DeclarationError: Undeclared identifier."
+        );
+    }
+}
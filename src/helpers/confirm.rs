@@ -0,0 +1,24 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use yansi::Paint;
+
+/// Asks a yes/no question on the terminal, anything other than `y` or `yes` is a no. Without a
+/// terminal the answer is always no, reading it would consume piped input instead.
+pub fn confirm(question: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        eprintln!("{question} [y/N] n (stdin is not a terminal)");
+        return false;
+    }
+
+    print!("{} ", Paint::yellow(format!("{question} [y/N]")));
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
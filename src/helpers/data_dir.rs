@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use chisel::session::ChiselSession;
+use foundry_config::Config;
+
+/// Name of the directory, inside of the foundry directory, that holds the files kept across
+/// sessions
+const DATA_DIR_NAME: &str = "chisel_gpt";
+
+/// Name of the directory, next to the chisel cache directory, that holds what ChiselGPT caches
+const CACHE_DIR_NAME: &str = "chisel_gpt";
+
+/// The directory for files that have to outlive the caches, i.e. `~/.foundry/chisel_gpt`. It is
+/// outside of the chisel cache directory, which `!clearcache` empties.
+pub fn data_dir() -> Option<PathBuf> {
+    Config::foundry_dir().map(|dir| dir.join(DATA_DIR_NAME))
+}
+
+/// The directory ChiselGPT caches to, i.e. `~/.foundry/cache/chisel_gpt`. It sits next to the
+/// chisel cache directory rather than inside of it, chisel takes every entry in there for a cached
+/// session.
+pub fn cache_dir() -> Option<PathBuf> {
+    let chisel_cache_dir = PathBuf::from(ChiselSession::cache_dir().ok()?);
+    Some(chisel_cache_dir.parent()?.join(CACHE_DIR_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_caches_next_to_the_chisel_sessions() {
+        let chisel_cache_dir = PathBuf::from(ChiselSession::cache_dir().unwrap());
+        let cache_dir = cache_dir().unwrap();
+
+        assert!(!cache_dir.starts_with(&chisel_cache_dir));
+        assert_eq!(cache_dir.parent(), chisel_cache_dir.parent());
+    }
+}
@@ -0,0 +1,97 @@
+use similar::{ChangeTag, TextDiff};
+use yansi::Paint;
+
+/// Number of unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// How a recipe changed the session source
+#[derive(Debug, Clone)]
+pub struct SourceChange {
+    pub request: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl SourceChange {
+    /// The change as a coloured unified diff of the `!source` output, `None` if the source didn't
+    /// change
+    pub fn render(&self) -> Option<String> {
+        (self.before != self.after)
+            .then(|| render_diff(&self.before, &self.after, "a/!source", "b/!source"))
+    }
+}
+
+/// Renders a coloured unified diff between two sources, the `---`/`+++` lines name them and each
+/// hunk starts with its `@@ -a,b +c,d @@` line ranges
+pub fn render_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut unified = diff.unified_diff();
+    unified.context_radius(CONTEXT_LINES);
+
+    let mut out = format!(
+        "{}\n{}\n",
+        Paint::red(format!("--- {old_name}")),
+        Paint::green(format!("+++ {new_name}"))
+    );
+
+    for hunk in unified.iter_hunks() {
+        out.push_str(&Paint::cyan(hunk.header()).to_string());
+        out.push('\n');
+
+        for change in hunk.iter_changes() {
+            let line = change.to_string_lossy();
+            let line = line.trim_end_matches('\n');
+
+            let rendered = match change.tag() {
+                ChangeTag::Delete => Paint::red(format!("-{line}")).to_string(),
+                ChangeTag::Insert => Paint::green(format!("+{line}")).to_string(),
+                ChangeTag::Equal => format!(" {line}"),
+            };
+
+            out.push_str(&rendered);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::command_helper::strip_ansi;
+
+    #[test]
+    fn it_renders_source_changes() {
+        let change = SourceChange {
+            request: "!chat set x to 2".to_string(),
+            before: "uint256 x = 1;\nuint256 y = 1;\n".to_string(),
+            after: "uint256 x = 1;\nuint256 y = 1;\nx = 2;\n".to_string(),
+        };
+        assert_eq!(
+            strip_ansi(&change.render().unwrap()),
+            "--- a/!source\n+++ b/!source\n@@ -1,2 +1,3 @@\n uint256 x = 1;\n uint256 y = 1;\n+x = 2;\n"
+        );
+
+        let unchanged = SourceChange {
+            after: change.before.clone(),
+            ..change
+        };
+        assert_eq!(unchanged.render(), None);
+    }
+
+    #[test]
+    fn it_starts_each_hunk_with_its_line_ranges() {
+        let before: String = (1..=20).map(|n| format!("uint256 x{n} = {n};\n")).collect();
+        let after = before
+            .replace("x2 = 2;", "x2 = 20;")
+            .replace("x18 = 18;", "x18 = 180;");
+
+        let hunks: Vec<String> = strip_ansi(&render_diff(&before, &after, "old", "new"))
+            .lines()
+            .filter(|line| line.starts_with("@@"))
+            .map(str::to_string)
+            .collect();
+        assert_eq!(hunks, vec!["@@ -1,5 +1,5 @@", "@@ -15,6 +15,6 @@"]);
+    }
+}
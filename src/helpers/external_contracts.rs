@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+use chisel::prelude::{ChiselCommand, COMMAND_LEADER};
+use solang_parser::lexer::{Lexer, Token};
+
+use super::source_index::find_definition;
+
+/// A contract whose interface was fetched into the session with `!fetch <address> <name>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalContract {
+    pub address: String,
+    pub name: String,
+}
+
+/// The contracts fetched during the session, in the order they were fetched
+#[derive(Debug, Clone, Default)]
+pub struct ExternalContracts {
+    contracts: Vec<ExternalContract>,
+}
+
+impl ExternalContracts {
+    /// Records the contract fetched by `line`, if it is a `!fetch` command. Fetching another
+    /// address under the same name replaces the interface, so it replaces the entry too.
+    pub fn record(&mut self, line: &str) -> Option<&ExternalContract> {
+        let (address, name) = fetched_contract(line)?;
+
+        self.contracts.retain(|contract| contract.name != name);
+        self.contracts.push(ExternalContract {
+            address: address.to_string(),
+            name: name.to_string(),
+        });
+        self.contracts.last()
+    }
+
+    pub fn clear(&mut self) {
+        self.contracts.clear();
+    }
+
+    /// The fetched contracts along with the signatures of their functions, read from the
+    /// interfaces in `source`. Contracts whose interface is no longer part of the source are left
+    /// out.
+    pub fn describe(&self, source: &str) -> Vec<(&ExternalContract, Vec<String>)> {
+        self.contracts
+            .iter()
+            .filter_map(|contract| {
+                let interface = find_definition(source, &contract.name)?;
+                Some((contract, function_signatures(&interface)))
+            })
+            .collect()
+    }
+}
+
+/// The address and name of a `!fetch <address> <name>` command
+pub fn fetched_contract(line: &str) -> Option<(&str, &str)> {
+    let mut words = line.trim().strip_prefix(COMMAND_LEADER)?.split_whitespace();
+
+    match ChiselCommand::from_str(words.next()?) {
+        Ok(ChiselCommand::Fetch) => Some((words.next()?, words.next()?)),
+        _ => None,
+    }
+}
+
+/// The function declarations of an interface, i.e. `function balanceOf(address) external view
+/// returns (uint256)`, each on a single line
+fn function_signatures(interface: &str) -> Vec<String> {
+    let mut comments = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut signatures = Vec::new();
+    let mut start: Option<usize> = None;
+
+    for (offset, token, end) in
+        Lexer::new(interface, 0, &mut comments, &mut errors).map_while(Result::ok)
+    {
+        match (token, start) {
+            (Token::Function, None) => start = Some(offset),
+            (Token::Semicolon | Token::OpenCurlyBrace, Some(function_start)) => {
+                let declaration = &interface[function_start..end - 1];
+                let declaration = declaration.split_whitespace().collect::<Vec<_>>().join(" ");
+                signatures.push(declaration.replace("( ", "(").replace(" )", ")"));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    signatures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "interface Router {
+    struct Route { address from; address to; }
+
+    function WETH() external view returns (address);
+    function swapExactETHForTokens(
+        uint256 amountOutMin,
+        address[] calldata path,
+        address to,
+        uint256 deadline
+    ) external payable returns (uint256[] memory amounts);
+    event Swapped(address indexed to);
+}
+
+contract REPL {
+    function run() public {}
+}";
+
+    #[test]
+    fn it_records_fetched_contracts() {
+        let mut contracts = ExternalContracts::default();
+
+        assert!(contracts.record("uint256 x = 1;").is_none());
+        assert!(contracts.record("!fe 0x1 Router").is_some());
+        assert!(contracts
+            .record("!fetch 0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D Router")
+            .is_some());
+        assert!(contracts.record("!fetch 0x2").is_none());
+
+        assert_eq!(
+            contracts.contracts,
+            vec![ExternalContract {
+                address: "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D".to_string(),
+                name: "Router".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn it_describes_the_functions_of_fetched_interfaces() {
+        let mut contracts = ExternalContracts::default();
+        contracts.record("!fetch 0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D Router");
+        contracts.record("!fetch 0x6B175474E89094C44Da98b954EedeAC495271d0F Dai");
+
+        let described = contracts.describe(SOURCE);
+
+        // The interface of `Dai` isn't in the source, i.e. the fetch failed
+        assert_eq!(described.len(), 1);
+        assert_eq!(described[0].0.name, "Router");
+        assert_eq!(
+            described[0].1,
+            vec![
+                "function WETH() external view returns (address)",
+                "function swapExactETHForTokens(uint256 amountOutMin, address[] calldata path, address to, uint256 deadline) external payable returns (uint256[] memory amounts)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::{sync::OnceLock, time::Duration};
+
+use chisel::prelude::ChiselDispatcher;
+use regex::Regex;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use super::rpc::RpcAliases;
+
+/// How long to wait for the node, the chat request waits on it
+const RPC_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Names of the chains the model is most likely to be asked about, by chain id
+const CHAINS: &[(u64, &str)] = &[
+    (1, "Ethereum mainnet"),
+    (10, "Optimism"),
+    (56, "BNB Smart Chain"),
+    (100, "Gnosis"),
+    (137, "Polygon"),
+    (8453, "Base"),
+    (42161, "Arbitrum One"),
+    (43114, "Avalanche C-Chain"),
+    (11155111, "Sepolia"),
+];
+
+/// What the model needs to know about the chain a session is forked from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForkInfo {
+    /// The `rpc_endpoints` alias of the fork URL, if it has one
+    pub alias: Option<String>,
+    pub chain_id: Option<u64>,
+    /// The block the fork is pinned to, or the latest block when the fork was first queried
+    pub block_number: Option<u64>,
+}
+
+impl ForkInfo {
+    /// Queries the node at `url`, `pinned_block` is the block the fork was created at, if any
+    pub async fn fetch(url: &str, pinned_block: Option<u64>) -> Self {
+        let block_number = match pinned_block {
+            Some(block) => Some(block),
+            None => request_number(url, "eth_blockNumber").await,
+        };
+
+        Self {
+            alias: None,
+            chain_id: request_number(url, "eth_chainId").await,
+            block_number,
+        }
+    }
+
+    pub fn chain_name(&self) -> Option<&'static str> {
+        CHAINS
+            .iter()
+            .find(|(chain_id, _)| Some(*chain_id) == self.chain_id)
+            .map(|(_, name)| *name)
+    }
+}
+
+/// A fork the session can be on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActiveFork {
+    pub url: String,
+    /// The block the fork was created at, `None` for the latest block
+    pub block_number: Option<u64>,
+}
+
+/// The fork the session is on once its source has run, if it is forked. Chisel runs the whole
+/// source for every input, so the last `vm.createSelectFork` in it wins over the fork the session
+/// was started on or switched to with `!fork`.
+pub fn active_fork(dispatcher: &ChiselDispatcher, aliases: &RpcAliases) -> Option<ActiveFork> {
+    let source = dispatcher.session.session_source.as_ref()?;
+    if let Some(fork) = selected_fork(&source.run_code, aliases) {
+        return Some(fork);
+    }
+
+    let evm_opts = &source.config.evm_opts;
+    let url = source
+        .config
+        .backend
+        .as_ref()
+        .and_then(|backend| backend.active_fork_url())
+        .or_else(|| evm_opts.fork_url.clone())?;
+    let block_number = evm_opts
+        .fork_block_number
+        .filter(|_| evm_opts.fork_url.as_ref() == Some(&url));
+
+    Some(ActiveFork { url, block_number })
+}
+
+/// The fork the last `vm.createSelectFork` in `run_code` selects, aliases resolved to their URL
+fn selected_fork(run_code: &str, aliases: &RpcAliases) -> Option<ActiveFork> {
+    static SELECT_FORK_RE: OnceLock<Regex> = OnceLock::new();
+    let select_fork_re = SELECT_FORK_RE.get_or_init(|| {
+        Regex::new(r#"\bcreateSelectFork\s*\(\s*"([^"]*)"\s*(?:,\s*([0-9_]+)\s*)?\)"#).unwrap()
+    });
+
+    let captures = select_fork_re.captures_iter(run_code).last()?;
+    let target = &captures[1];
+
+    Some(ActiveFork {
+        url: aliases.url_of(target).unwrap_or(target).to_string(),
+        block_number: captures
+            .get(2)
+            .and_then(|block| block.as_str().replace('_', "").parse().ok()),
+    })
+}
+
+/// Calls a JSON-RPC method of the node at `url` that returns a hex encoded number
+async fn request_number(url: &str, method: &str) -> Option<u64> {
+    let response: Value = Client::builder()
+        .timeout(RPC_TIMEOUT)
+        .build()
+        .ok()?
+        .post(url)
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": [],
+        }))
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+
+    u64::from_str_radix(response["result"].as_str()?.trim_start_matches("0x"), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chisel::prelude::SessionSource;
+
+    use super::*;
+    use crate::completion::mock_server::{mock_server, request_body, MockResponse};
+
+    #[test]
+    fn it_follows_the_fork_selected_last() {
+        let aliases = RpcAliases::from_iter([(
+            "polygon".to_string(),
+            Some("https://polygon-rpc.com".to_string()),
+        )]);
+        let mut dispatcher = ChiselDispatcher::default();
+        assert_eq!(active_fork(&dispatcher, &aliases), None);
+
+        let mut source = SessionSource::default();
+        source.config.evm_opts.fork_url = Some("https://eth.llamarpc.com".to_string());
+        source.config.evm_opts.fork_block_number = Some(18_000_000);
+        dispatcher.session.session_source = Some(source.clone());
+        assert_eq!(
+            active_fork(&dispatcher, &aliases),
+            Some(ActiveFork {
+                url: "https://eth.llamarpc.com".to_string(),
+                block_number: Some(18_000_000),
+            })
+        );
+
+        source.run_code = "vm.createSelectFork(\"https://eth.llamarpc.com\");\nuint256 id = vm.createSelectFork(\"polygon\", 50_000_000);".to_string();
+        dispatcher.session.session_source = Some(source);
+        assert_eq!(
+            active_fork(&dispatcher, &aliases),
+            Some(ActiveFork {
+                url: "https://polygon-rpc.com".to_string(),
+                block_number: Some(50_000_000),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_the_fork_info() {
+        let (url, server) = mock_server(vec![
+            MockResponse::json(
+                "200 OK",
+                json!({"jsonrpc": "2.0", "id": 1, "result": "0x2faf080"}),
+            ),
+            MockResponse::json(
+                "200 OK",
+                json!({"jsonrpc": "2.0", "id": 1, "result": "0x89"}),
+            ),
+        ])
+        .await;
+
+        let info = ForkInfo::fetch(&url, None).await;
+        assert_eq!(info.chain_id, Some(137));
+        assert_eq!(info.block_number, Some(50_000_000));
+        assert_eq!(info.chain_name(), Some("Polygon"));
+
+        let methods: Vec<Value> = server
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| request_body(request)["method"].clone())
+            .collect();
+        assert_eq!(methods, vec!["eth_blockNumber", "eth_chainId"]);
+    }
+}
@@ -1,3 +1,19 @@
+pub mod address_book;
+pub mod cheatcodes;
+pub mod checkpoints;
 pub mod command_helper;
+pub mod compile_check;
+pub mod confirm;
+pub mod data_dir;
+pub mod diff;
 pub mod dispatch;
+pub mod external_contracts;
+pub mod fork;
+pub mod policy;
+pub mod prompt_file;
+pub mod prose;
+pub mod recipe;
+pub mod rpc;
+pub mod session;
+pub mod source_index;
 pub mod split_commands;
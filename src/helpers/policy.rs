@@ -0,0 +1,830 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chisel::{prelude::COMMAND_LEADER, solidity_helper::SolidityHelper};
+use serde::{Deserialize, Serialize};
+use solang_parser::{
+    lexer::{Lexer, Token},
+    pt::{
+        CatchClause, ContractPart, Expression, FunctionAttribute, FunctionDefinition, SourceUnit,
+        SourceUnitPart, Statement, VariableDefinition, YulBlock, YulExpression, YulFunctionCall,
+        YulStatement, YulSwitchOptions,
+    },
+};
+use yansi::Paint;
+
+use super::{confirm::confirm, data_dir::data_dir, recipe::render_ingredient};
+
+/// Name of the file, inside of the data directory, policy decisions are appended to
+const POLICY_LOG_FILE_NAME: &str = "policy.log";
+
+/// The address cheatcodes are called on
+const CHEATCODE_ADDRESS: &str = "0x7109709ecfa91a80626ff3989d68f67f5b1dd12d";
+
+/// Cheatcodes that run commands on the host
+const SHELL_CHEATCODES: &[&str] = &["ffi"];
+
+/// Chisel commands that run commands on the host, each with its aliases
+const SHELL_COMMANDS: &[&[&str]] = &[&["exec", "e"], &["edit"]];
+
+/// Chisel commands that read or write files, each with its aliases
+const FILE_SYSTEM_COMMANDS: &[&[&str]] = &[
+    &["export", "ex"],
+    &["save", "s"],
+    &["load", "l"],
+    &["clearcache", "cc"],
+];
+
+/// Yul builtins that call other contracts, any of them can call the cheatcodes
+const ASSEMBLY_CALLS: &[&str] = &["call", "staticcall", "delegatecall", "callcode"];
+
+/// Cheatcodes that read or write files
+const FILE_SYSTEM_CHEATCODES: &[&str] = &[
+    "readFile",
+    "readFileBinary",
+    "readLine",
+    "writeFile",
+    "writeFileBinary",
+    "writeLine",
+    "writeJson",
+    "closeFile",
+    "removeFile",
+    "fsMetadata",
+    "readDir",
+    "createDir",
+    "removeDir",
+];
+
+/// Cheatcodes that read or write environment variables
+const ENVIRONMENT_CHEATCODES: &[&str] = &[
+    "envBool",
+    "envUint",
+    "envInt",
+    "envAddress",
+    "envBytes32",
+    "envString",
+    "envBytes",
+    "envOr",
+    "setEnv",
+];
+
+/// What happens to an ingredient that matches a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    /// Dispatched only once the user agrees
+    Confirm,
+    Block,
+}
+
+/// Access to the host an ingredient can gain through cheatcodes or chisel commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Shell,
+    FileSystem,
+    Environment,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shell => write!(f, "shell access"),
+            Self::FileSystem => write!(f, "file system access"),
+            Self::Environment => write!(f, "environment access"),
+        }
+    }
+}
+
+/// The rules model generated ingredients are checked against before they are dispatched, read
+/// from the `[policy]` table of `chisel_gpt.toml`, i.e.
+///
+/// ```toml
+/// [policy]
+/// shell = "block"
+/// file_system = "confirm"
+/// environment = "allow"
+///
+/// [policy.rules]
+/// "vm.etch" = "confirm"
+/// "vm.envOr" = "allow"
+/// ```
+///
+/// Rules for single cheatcodes (`vm.<name>`) or chisel commands (`!<name>`) take precedence over
+/// the capability they fall under.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub shell: Action,
+    pub file_system: Action,
+    pub environment: Action,
+    pub rules: BTreeMap<String, Action>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            shell: Action::Block,
+            file_system: Action::Confirm,
+            environment: Action::Confirm,
+            rules: BTreeMap::new(),
+        }
+    }
+}
+
+/// A cheatcode or chisel command in an ingredient that the policy doesn't simply allow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// The cheatcode or command, i.e. `vm.ffi` or `!exec`
+    pub name: String,
+    pub capability: Option<Capability>,
+    pub action: Action,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.capability {
+            Some(capability) => write!(f, "{} ({capability})", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// An ingredient the policy kept from being dispatched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub ingredient: usize,
+    pub reason: String,
+}
+
+/// A policy decision as written to the log
+#[derive(Debug, Serialize)]
+struct LogEntry<'a> {
+    timestamp: u64,
+    ingredient: &'a str,
+    name: &'a str,
+    capability: Option<Capability>,
+    decision: &'a str,
+}
+
+impl Policy {
+    fn action_for(&self, name: &str, capability: Option<Capability>) -> Action {
+        if let Some(action) = self.rules.get(name) {
+            return *action;
+        }
+
+        match capability {
+            Some(Capability::Shell) => self.shell,
+            Some(Capability::FileSystem) => self.file_system,
+            Some(Capability::Environment) => self.environment,
+            None => Action::Allow,
+        }
+    }
+
+    /// The cheatcodes and chisel commands used by an ingredient that have to be blocked or
+    /// confirmed. Cheatcodes are matched by name on any receiver, so aliases of `vm` and
+    /// signatures passed to `abi.encodeWithSignature` are caught as well. Calls from inline
+    /// assembly count as shell access unless they go to a literal address other than the
+    /// cheatcode address, there is no telling which cheatcode they call.
+    pub fn inspect(&self, ingredient: &str) -> Vec<Finding> {
+        let ingredient = ingredient.trim();
+
+        if let Some(command) = ingredient.strip_prefix(COMMAND_LEADER) {
+            let command = command.split_whitespace().next().unwrap_or_default();
+            let (name, capability) = command_capability(command);
+            return self
+                .finding(format!("{COMMAND_LEADER}{name}"), capability)
+                .into_iter()
+                .collect();
+        }
+
+        let collector = collect(ingredient);
+
+        let mut findings: Vec<Finding> = Vec::new();
+        for call in collector.assembly_calls {
+            if let Some(finding) = self.finding(format!("assembly.{call}"), Some(Capability::Shell))
+            {
+                if !findings.contains(&finding) {
+                    findings.push(finding);
+                }
+            }
+        }
+
+        for name in collector.names {
+            let capability = if SHELL_CHEATCODES.contains(&name.as_str()) {
+                Some(Capability::Shell)
+            } else if FILE_SYSTEM_CHEATCODES.contains(&name.as_str()) {
+                Some(Capability::FileSystem)
+            } else if ENVIRONMENT_CHEATCODES.contains(&name.as_str()) {
+                Some(Capability::Environment)
+            } else {
+                None
+            };
+
+            if let Some(finding) = self.finding(format!("vm.{name}"), capability) {
+                if !findings.contains(&finding) {
+                    findings.push(finding);
+                }
+            }
+        }
+
+        findings
+    }
+
+    fn finding(&self, name: String, capability: Option<Capability>) -> Option<Finding> {
+        let action = self.action_for(&name, capability);
+        (action != Action::Allow).then_some(Finding {
+            name,
+            capability,
+            action,
+        })
+    }
+
+    /// Checks the ingredients of a recipe in order, asking for confirmation where the policy says
+    /// so. Returns the first ingredient that was blocked or declined, the recipe must not be
+    /// dispatched if there is one. Every decision is logged.
+    pub fn enforce(&self, ingredients: &[String]) -> Option<Rejection> {
+        for (index, ingredient) in ingredients.iter().enumerate() {
+            let number = index + 1;
+            let findings = self.inspect(ingredient);
+            if findings.is_empty() {
+                continue;
+            }
+
+            let uses = findings
+                .iter()
+                .map(Finding::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            print!(
+                "\n{}",
+                render_ingredient(number, ingredient, &SolidityHelper::highlight(ingredient))
+            );
+
+            if findings
+                .iter()
+                .any(|finding| finding.action == Action::Block)
+            {
+                eprintln!(
+                    "{}",
+                    Paint::red(format!(
+                        "Blocked by policy, ingredient {number} uses {uses}"
+                    ))
+                );
+                log_decisions(ingredient, &findings, "blocked");
+
+                return Some(Rejection {
+                    ingredient: number,
+                    reason: format!("Blocked by policy, uses {uses}"),
+                });
+            }
+
+            if !confirm(&format!(
+                "Ingredient {number} uses {uses}, dispatch the recipe anyway?"
+            )) {
+                log_decisions(ingredient, &findings, "declined");
+
+                return Some(Rejection {
+                    ingredient: number,
+                    reason: format!("Declined, uses {uses}"),
+                });
+            }
+            log_decisions(ingredient, &findings, "confirmed");
+        }
+
+        None
+    }
+}
+
+/// The capabilities an ingredient uses, whether or not the policy allows them
+pub fn capabilities(ingredient: &str) -> Vec<Capability> {
+    let everything_confirmed = Policy {
+        shell: Action::Confirm,
+        file_system: Action::Confirm,
+        environment: Action::Confirm,
+        rules: BTreeMap::new(),
+    };
+
+    let mut capabilities = Vec::new();
+    for finding in everything_confirmed.inspect(ingredient) {
+        if let Some(capability) = finding.capability {
+            if !capabilities.contains(&capability) {
+                capabilities.push(capability);
+            }
+        }
+    }
+    capabilities
+}
+
+/// The path decisions are logged to
+pub fn log_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(POLICY_LOG_FILE_NAME))
+}
+
+/// The full name of a chisel command, aliases share the rule of the full name, and the
+/// capability it needs
+fn command_capability(command: &str) -> (&str, Option<Capability>) {
+    let commands = SHELL_COMMANDS
+        .iter()
+        .map(|names| (names, Capability::Shell))
+        .chain(
+            FILE_SYSTEM_COMMANDS
+                .iter()
+                .map(|names| (names, Capability::FileSystem)),
+        );
+
+    for (names, capability) in commands {
+        if names.contains(&command) {
+            return (names[0], Some(capability));
+        }
+    }
+
+    (command, None)
+}
+
+/// Appends the decision on each finding to the policy log, one JSON object per line
+fn log_decisions(ingredient: &str, findings: &[Finding], decision: &str) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    let mut lines = String::new();
+    for finding in findings {
+        let entry = LogEntry {
+            timestamp,
+            ingredient,
+            name: &finding.name,
+            capability: finding.capability,
+            decision,
+        };
+        if let Ok(line) = serde_json::to_string(&entry) {
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+    }
+
+    let result = log_path()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Failed to resolve the foundry directory",
+            )
+        })
+        .and_then(|path| {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(lines.as_bytes())
+        });
+
+    if let Err(e) = result {
+        eprintln!(
+            "{}",
+            Paint::red(format!("Failed to log policy decision: {e}"))
+        );
+    }
+}
+
+/// Whether a hex literal is the cheatcode address, leading zeros and case aside
+fn is_cheatcode_address(literal: &str) -> bool {
+    let digits = |literal: &str| {
+        literal
+            .trim_start_matches("0x")
+            .trim_start_matches('0')
+            .to_lowercase()
+    };
+    digits(literal) == digits(CHEATCODE_ADDRESS)
+}
+
+/// Collects the names of the members accessed by an ingredient, along with the function names of
+/// signatures in string literals (`"ffi(string[])"`), and the calls made from inline assembly
+fn collect(ingredient: &str) -> MemberCollector {
+    let mut collector = MemberCollector::default();
+
+    // Ingredients are definitions, or statements that chisel places in `run()`
+    let parsed = solang_parser::parse(ingredient, 0)
+        .or_else(|_| solang_parser::parse(&format!("function run() {{\n{ingredient}\n}}"), 0))
+        .or_else(|_| solang_parser::parse(&format!("function run() {{\n{ingredient};\n}}"), 0));
+
+    match parsed {
+        Ok((unit, _)) => collector.source_unit(&unit),
+        // Chisel may still make sense of input that doesn't parse on its own, so fall back to
+        // the tokens rather than letting it through unchecked
+        Err(_) => {
+            let mut comments = Vec::new();
+            let mut errors = Vec::new();
+            let tokens: Vec<Token> = Lexer::new(ingredient, 0, &mut comments, &mut errors)
+                .filter_map(Result::ok)
+                .map(|(_, token, _)| token)
+                .collect();
+
+            for window in tokens.windows(2) {
+                if let [Token::Member, Token::Identifier(name)] = window {
+                    collector.names.push(name.to_string());
+                }
+            }
+            // Without a parse tree the targets of assembly calls are unknown
+            if tokens.contains(&Token::Assembly) {
+                for token in &tokens {
+                    if let Token::Identifier(name) = token {
+                        if ASSEMBLY_CALLS.contains(name) {
+                            collector.assembly_calls.push(name.to_string());
+                        }
+                    }
+                }
+            }
+            for token in tokens {
+                if let Token::StringLiteral(_, string) = token {
+                    collector.string_literal(string);
+                }
+            }
+        }
+    }
+
+    collector
+}
+
+/// Walks the parse tree of an ingredient and collects the names of accessed members
+#[derive(Debug, Default)]
+struct MemberCollector {
+    names: Vec<String>,
+    /// The Yul builtins inline assembly calls contracts with that could be the cheatcodes
+    assembly_calls: Vec<String>,
+}
+
+impl MemberCollector {
+    fn source_unit(&mut self, unit: &SourceUnit) {
+        for part in &unit.0 {
+            match part {
+                SourceUnitPart::ContractDefinition(contract) => {
+                    for base in &contract.base {
+                        self.expressions(base.args.iter().flatten());
+                    }
+                    for part in &contract.parts {
+                        match part {
+                            ContractPart::FunctionDefinition(function) => self.function(function),
+                            ContractPart::VariableDefinition(variable) => self.variable(variable),
+                            _ => {}
+                        }
+                    }
+                }
+                SourceUnitPart::FunctionDefinition(function) => self.function(function),
+                SourceUnitPart::VariableDefinition(variable) => self.variable(variable),
+                _ => {}
+            }
+        }
+    }
+
+    fn function(&mut self, function: &FunctionDefinition) {
+        for attribute in &function.attributes {
+            if let FunctionAttribute::BaseOrModifier(_, base) = attribute {
+                self.expressions(base.args.iter().flatten());
+            }
+        }
+        if let Some(body) = &function.body {
+            self.statement(body);
+        }
+    }
+
+    fn variable(&mut self, variable: &VariableDefinition) {
+        if let Some(initializer) = &variable.initializer {
+            self.expression(initializer);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Block { statements, .. } => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            Statement::Args(_, args) | Statement::RevertNamedArgs(_, _, args) => {
+                self.expressions(args.iter().map(|arg| &arg.expr));
+            }
+            Statement::If(_, condition, then, otherwise) => {
+                self.expression(condition);
+                self.statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            Statement::While(_, condition, body) | Statement::DoWhile(_, body, condition) => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Statement::Expression(_, expression) | Statement::Emit(_, expression) => {
+                self.expression(expression);
+            }
+            Statement::VariableDefinition(_, _, initializer)
+            | Statement::Return(_, initializer) => {
+                self.expressions(initializer);
+            }
+            Statement::For(_, init, condition, next, body) => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                self.expressions(condition.iter().chain(next).map(Box::as_ref));
+                if let Some(body) = body {
+                    self.statement(body);
+                }
+            }
+            Statement::Revert(_, _, args) => self.expressions(args),
+            Statement::Try(_, expression, returns, catches) => {
+                self.expression(expression);
+                if let Some((_, body)) = returns {
+                    self.statement(body);
+                }
+                for catch in catches {
+                    match catch {
+                        CatchClause::Simple(_, _, body) | CatchClause::Named(_, _, _, body) => {
+                            self.statement(body)
+                        }
+                    }
+                }
+            }
+            Statement::Assembly { block, .. } => self.yul_block(block),
+            Statement::Continue(_) | Statement::Break(_) | Statement::Error(_) => {}
+        }
+    }
+
+    fn yul_block(&mut self, block: &YulBlock) {
+        for statement in &block.statements {
+            self.yul_statement(statement);
+        }
+    }
+
+    fn yul_statement(&mut self, statement: &YulStatement) {
+        match statement {
+            YulStatement::Assign(_, _, value) => self.yul_expression(value),
+            YulStatement::VariableDeclaration(_, _, value) => {
+                if let Some(value) = value {
+                    self.yul_expression(value);
+                }
+            }
+            YulStatement::If(_, condition, block) => {
+                self.yul_expression(condition);
+                self.yul_block(block);
+            }
+            YulStatement::For(for_loop) => {
+                self.yul_block(&for_loop.init_block);
+                self.yul_expression(&for_loop.condition);
+                self.yul_block(&for_loop.post_block);
+                self.yul_block(&for_loop.execution_block);
+            }
+            YulStatement::Switch(switch) => {
+                self.yul_expression(&switch.condition);
+                for option in switch.cases.iter().chain(&switch.default) {
+                    match option {
+                        YulSwitchOptions::Case(_, _, block)
+                        | YulSwitchOptions::Default(_, block) => self.yul_block(block),
+                    }
+                }
+            }
+            YulStatement::Block(block) => self.yul_block(block),
+            YulStatement::FunctionDefinition(function) => self.yul_block(&function.body),
+            YulStatement::FunctionCall(call) => self.yul_call(call),
+            YulStatement::Leave(_)
+            | YulStatement::Break(_)
+            | YulStatement::Continue(_)
+            | YulStatement::Error(_) => {}
+        }
+    }
+
+    fn yul_expression(&mut self, expression: &YulExpression) {
+        match expression {
+            YulExpression::FunctionCall(call) => self.yul_call(call),
+            YulExpression::SuffixAccess(_, expression, _) => self.yul_expression(expression),
+            YulExpression::BoolLiteral(..)
+            | YulExpression::NumberLiteral(..)
+            | YulExpression::HexNumberLiteral(..)
+            | YulExpression::HexStringLiteral(..)
+            | YulExpression::StringLiteral(..)
+            | YulExpression::Variable(_) => {}
+        }
+    }
+
+    fn yul_call(&mut self, call: &YulFunctionCall) {
+        // The target is the second argument of every call builtin, only a literal can be told
+        // apart from the cheatcode address
+        let name = call.id.name.as_str();
+        if ASSEMBLY_CALLS.contains(&name) {
+            let calls_other_contract = match call.arguments.get(1) {
+                Some(YulExpression::HexNumberLiteral(_, address, _)) => {
+                    !is_cheatcode_address(address)
+                }
+                _ => false,
+            };
+            if !calls_other_contract {
+                self.assembly_calls.push(name.to_string());
+            }
+        }
+
+        for argument in &call.arguments {
+            self.yul_expression(argument);
+        }
+    }
+
+    fn expressions<'a>(&mut self, expressions: impl IntoIterator<Item = &'a Expression>) {
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::MemberAccess(_, object, member) => {
+                self.names.push(member.name.clone());
+                self.expression(object);
+            }
+            Expression::FunctionCall(_, callee, args) => {
+                self.expression(callee);
+                self.expressions(args);
+            }
+            Expression::FunctionCallBlock(_, callee, block) => {
+                self.expression(callee);
+                self.statement(block);
+            }
+            Expression::NamedFunctionCall(_, callee, args) => {
+                self.expression(callee);
+                self.expressions(args.iter().map(|arg| &arg.expr));
+            }
+            Expression::ArraySubscript(_, array, index) => {
+                self.expression(array);
+                self.expressions(index.iter().map(Box::as_ref));
+            }
+            Expression::ArraySlice(_, array, from, to) => {
+                self.expression(array);
+                self.expressions(from.iter().chain(to).map(Box::as_ref));
+            }
+            Expression::ConditionalOperator(_, condition, then, otherwise) => {
+                self.expressions([condition, then, otherwise].map(Box::as_ref));
+            }
+            Expression::ArrayLiteral(_, items) => self.expressions(items),
+            Expression::List(_, items) => {
+                for (_, parameter) in items {
+                    if let Some(parameter) = parameter {
+                        self.expression(&parameter.ty);
+                    }
+                }
+            }
+            Expression::StringLiteral(parts) => {
+                let string: String = parts.iter().map(|part| part.string.as_str()).collect();
+                self.string_literal(&string);
+            }
+            _ => {
+                let (left, right) = expression.components();
+                self.expressions(left.into_iter().chain(right));
+            }
+        }
+    }
+
+    /// Picks up the function name of a signature, i.e. `ffi` in `"ffi(string[])"`
+    fn string_literal(&mut self, string: &str) {
+        if let Some((name, _)) = string.split_once('(') {
+            let name = name.trim();
+            if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                self.names.push(name.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(findings: Vec<Finding>) -> Vec<String> {
+        findings.into_iter().map(|finding| finding.name).collect()
+    }
+
+    #[test]
+    fn it_finds_cheatcodes_in_statements_and_definitions() {
+        let policy = Policy::default();
+
+        assert_eq!(
+            names(policy.inspect("string[] memory cmd = new string[](1);")),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            names(policy.inspect("bytes memory out = vm.ffi(cmd)")),
+            vec!["vm.ffi"]
+        );
+        assert_eq!(
+            names(policy.inspect(
+                "contract Reader { function key() public returns (string memory) { if (true) { return vm.envString(\"KEY\"); } } }"
+            )),
+            vec!["vm.envString"]
+        );
+        assert_eq!(
+            names(policy.inspect(
+                "address(vm).call(abi.encodeWithSignature(\"writeFile(string,string)\", path, data));"
+            )),
+            vec!["vm.writeFile"]
+        );
+        assert_eq!(names(policy.inspect("!e rm -rf /")), vec!["!exec"]);
+        assert_eq!(
+            names(policy.inspect("!fetch 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2 WETH")),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn it_finds_calls_from_inline_assembly() {
+        let policy = Policy::default();
+
+        assert_eq!(
+            policy.inspect(
+                "assembly { let ok := call(gas(), 0x7109709ECfa91a80626fF3989D68f67F5b1DD12D, 0, 0, 0, 0, 0) }"
+            ),
+            vec![Finding {
+                name: "assembly.call".to_string(),
+                capability: Some(Capability::Shell),
+                action: Action::Block,
+            }]
+        );
+        assert_eq!(
+            names(policy.inspect(
+                "address target = address(vm);\nassembly { for { let i := 0 } lt(i, 1) { i := add(i, 1) } { if iszero(staticcall(gas(), target, 0, 0, 0, 0)) { revert(0, 0) } } }"
+            )),
+            vec!["assembly.staticcall"]
+        );
+        assert_eq!(
+            names(policy.inspect(
+                "assembly { pop(call(gas(), 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2, 0, 0, 0, 0, 0)) }"
+            )),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn it_finds_chisel_commands_that_touch_the_file_system() {
+        let policy = Policy::default();
+
+        for (command, name) in [
+            ("!save", "!save"),
+            ("!ex", "!export"),
+            ("!load 1", "!load"),
+            ("!cc", "!clearcache"),
+        ] {
+            assert_eq!(
+                policy.inspect(command),
+                vec![Finding {
+                    name: name.to_string(),
+                    capability: Some(Capability::FileSystem),
+                    action: Action::Confirm,
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn it_lists_capabilities_whatever_the_policy() {
+        assert_eq!(
+            capabilities("vm.setEnv(\"A\", \"1\"); vm.writeFile(path, data); vm.envUint(\"A\");"),
+            vec![Capability::Environment, Capability::FileSystem]
+        );
+        assert!(capabilities("vm.deal(address(this), 1 ether);").is_empty());
+    }
+
+    #[test]
+    fn it_applies_rules() {
+        let policy = Policy {
+            environment: Action::Allow,
+            rules: BTreeMap::from([
+                ("vm.etch".to_string(), Action::Confirm),
+                ("vm.readFile".to_string(), Action::Block),
+            ]),
+            ..Policy::default()
+        };
+
+        assert_eq!(
+            policy.inspect("vm.etch(target, code); vm.envUint(\"X\"); vm.readFile(path)"),
+            vec![
+                Finding {
+                    name: "vm.etch".to_string(),
+                    capability: None,
+                    action: Action::Confirm,
+                },
+                Finding {
+                    name: "vm.readFile".to_string(),
+                    capability: Some(Capability::FileSystem),
+                    action: Action::Block,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use chisel::prelude::COMMAND_LEADER;
+use eyre::{eyre, Result};
+use rustyline::validate::ValidationResult;
+
+use super::{command_helper::CommandHelper, prose::looks_like_prose};
+use crate::commands::ChiselGptCommand;
+
+/// Groups lines into entries the way they are read from piped input. Commands take a single
+/// line, Solidity carries on to the next line while a brace, bracket or parenthesis is left open,
+/// and a `!chat` request carries on while the lines after it read like prose. Blank lines end a
+/// request and are skipped between entries.
+#[derive(Debug, Default)]
+pub struct EntryReader {
+    input: String,
+    /// Whether `input` is a `!chat` request that the next line may continue
+    request: bool,
+}
+
+impl EntryReader {
+    /// Adds a line, returns the entries it completes. A `!chat` request is only complete once
+    /// the line after it doesn't continue it.
+    pub fn push(&mut self, line: &str) -> Vec<String> {
+        let mut entries = Vec::new();
+
+        if self.request {
+            if !line.trim_start().starts_with(COMMAND_LEADER) && looks_like_prose(line) {
+                self.input.push('\n');
+                self.input.push_str(line.trim());
+                return entries;
+            }
+
+            self.request = false;
+            entries.push(std::mem::take(&mut self.input));
+        }
+
+        if self.input.is_empty() && line.trim().is_empty() {
+            return entries;
+        }
+
+        if !self.input.is_empty() {
+            self.input.push('\n');
+        }
+        self.input.push_str(line);
+
+        // Commands take a single line, a `'` in a request doesn't open a string
+        let incomplete = if self.input.trim_start().starts_with(COMMAND_LEADER) {
+            self.request = matches!(
+                ChiselGptCommand::parse(&self.input),
+                Some((ChiselGptCommand::Chat, _))
+            );
+            self.request
+        } else {
+            matches!(
+                CommandHelper::validate_closed(&self.input),
+                ValidationResult::Incomplete
+            )
+        };
+
+        if !incomplete {
+            entries.push(std::mem::take(&mut self.input));
+        }
+        entries
+    }
+
+    /// Ends the input, returns the `!chat` request that was still open. Fails with the Solidity
+    /// that was left open, if any.
+    pub fn finish(self) -> Result<Option<String>, String> {
+        if self.request {
+            Ok(Some(self.input))
+        } else if self.input.is_empty() {
+            Ok(None)
+        } else {
+            Err(self.input)
+        }
+    }
+}
+
+/// Splits a prompt file into its entries, `!chat` requests and chisel inputs, with an
+/// [EntryReader]. Fails if the file ends before the last entry was closed.
+pub fn parse_prompt_file(contents: &str) -> Result<Vec<String>> {
+    let mut reader = EntryReader::default();
+    let mut entries: Vec<String> = contents
+        .lines()
+        .map(str::trim_end)
+        .flat_map(|line| reader.push(line))
+        .collect();
+
+    match reader.finish() {
+        Ok(last) => {
+            entries.extend(last);
+            Ok(entries)
+        }
+        Err(input) => Err(eyre!(
+            "The prompt file ended before the last block was closed:\n{input}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_has_an_entry_per_line() {
+        assert_eq!(
+            parse_prompt_file(
+                "!f mainnet\n!chat wrap 1 ether into weth\nweth.balanceOf(address(this))\n\n"
+            )
+            .unwrap(),
+            vec![
+                "!f mainnet",
+                "!chat wrap 1 ether into weth",
+                "weth.balanceOf(address(this))",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_continues_entries_until_their_brackets_are_closed() {
+        let contents = "\n!f mainnet\n\nfunction double(uint256 x) public pure returns (uint256) {\n    return x * 2;\n}\n\n\n!chat double 21\n";
+
+        assert_eq!(
+            parse_prompt_file(contents).unwrap(),
+            vec![
+                "!f mainnet",
+                "function double(uint256 x) public pure returns (uint256) {\n    return x * 2;\n}",
+                "!chat double 21",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_mixes_single_line_entries_and_blocks() {
+        let contents = "!f mainnet\nuint256 x = 1;\n\nfunction double(uint256 y) public pure returns (uint256) {\n\n    return y * 2;\n}\ndouble(x)\n!chat explain what double(x) returns, don't guess\n";
+
+        assert_eq!(
+            parse_prompt_file(contents).unwrap(),
+            vec![
+                "!f mainnet",
+                "uint256 x = 1;",
+                "function double(uint256 y) public pure returns (uint256) {\n\n    return y * 2;\n}",
+                "double(x)",
+                "!chat explain what double(x) returns, don't guess",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_continues_requests_over_lines_that_read_like_prose() {
+        let contents = "!chat wrap 1 ether into weth\n    and approve the router to spend all of it\nweth.balanceOf(address(this))\n!chat deal me 1 ether\n\nthen send half of it to alice\n!chat explain the session\n";
+
+        assert_eq!(
+            parse_prompt_file(contents).unwrap(),
+            vec![
+                "!chat wrap 1 ether into weth\nand approve the router to spend all of it",
+                "weth.balanceOf(address(this))",
+                "!chat deal me 1 ether",
+                "then send half of it to alice",
+                "!chat explain the session",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_fails_on_an_unclosed_block() {
+        assert_eq!(
+            parse_prompt_file("!f mainnet\nfunction double(uint256 x) public {\n")
+                .unwrap_err()
+                .to_string(),
+            "The prompt file ended before the last block was closed:\nfunction double(uint256 x) public {"
+        );
+    }
+}
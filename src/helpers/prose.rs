@@ -0,0 +1,153 @@
+use std::sync::OnceLock;
+
+use chisel::prelude::COMMAND_LEADER;
+use regex::Regex;
+use solang_parser::lexer::{Lexer, Token};
+
+/// Minimum number of adjacent words for input to read as a sentence, declarations such as
+/// `IERC20 token` only ever put two identifiers next to each other
+const MIN_WORD_RUN: usize = 3;
+
+/// How a token counts towards the input being prose
+enum TokenKind {
+    /// Identifiers and keywords that double as plain English words
+    Word,
+    /// Punctuation that is at home in a sentence, along with literals
+    Neutral,
+    /// Operators, brackets and keywords that only show up in code
+    Code,
+}
+
+fn token_kind(token: &Token) -> TokenKind {
+    match token {
+        Token::Identifier(_)
+        | Token::Is
+        | Token::For
+        | Token::If
+        | Token::Else
+        | Token::Do
+        | Token::While
+        | Token::New
+        | Token::Return
+        | Token::Returns
+        | Token::Delete => TokenKind::Word,
+        Token::Comma
+        | Token::Question
+        | Token::Not
+        | Token::Colon
+        | Token::Number(..)
+        | Token::RationalNumber(..)
+        | Token::HexNumber(_)
+        | Token::StringLiteral(..)
+        | Token::AddressLiteral(_) => TokenKind::Neutral,
+        _ => TokenKind::Code,
+    }
+}
+
+/// Whether the input reads like a request in plain language rather than Solidity, i.e.
+/// `wrap 1 ether into weth` but not `uin256 x = 5`.
+///
+/// Errs on the side of Solidity: anything that parses, ends like a statement or block, or uses
+/// more operators than a sentence would is left for chisel to report on.
+pub fn looks_like_prose(input: &str) -> bool {
+    let input = input.trim();
+    if input.is_empty()
+        || input.starts_with(COMMAND_LEADER)
+        || input.ends_with(|c| matches!(c, ';' | '{' | '}'))
+        || parses_as_solidity(input)
+    {
+        return false;
+    }
+
+    // Contractions would otherwise open a string literal, i.e. `what's`
+    static CONTRACTION_RE: OnceLock<Regex> = OnceLock::new();
+    let contraction_re =
+        CONTRACTION_RE.get_or_init(|| Regex::new(r"([A-Za-z])'([A-Za-z])").unwrap());
+    let input = contraction_re.replace_all(input, "$1$2");
+
+    let mut comments = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut words = 0usize;
+    let mut code = 0usize;
+    let mut run = 0usize;
+    let mut longest_run = 0usize;
+
+    for token in Lexer::new(&input, 0, &mut comments, &mut errors) {
+        let Ok((_, token, _)) = token else {
+            return false;
+        };
+
+        match token_kind(&token) {
+            TokenKind::Word => {
+                words += 1;
+                run += 1;
+                longest_run = longest_run.max(run);
+            }
+            TokenKind::Neutral => run = 0,
+            TokenKind::Code => {
+                code += 1;
+                run = 0;
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return false;
+    }
+
+    // Questions about code, i.e. `what does weth.deposit() do?`, can mention a fair bit of it
+    if input.ends_with('?') {
+        longest_run >= MIN_WORD_RUN - 1 && code <= words
+    } else {
+        longest_run >= MIN_WORD_RUN && code * 2 <= words
+    }
+}
+
+/// Whether the input parses as a top level definition or as a statement inside of a function
+fn parses_as_solidity(input: &str) -> bool {
+    solang_parser::parse(input, 0).is_ok()
+        || solang_parser::parse(
+            &format!("contract REPL {{ function run() public {{ {input}; }} }}"),
+            0,
+        )
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_recognizes_requests() {
+        for input in [
+            "wrap 1 ether into weth",
+            "what's the balance of vitalik.eth",
+            "What does weth.deposit() do?",
+            "deploy a simple counter contract and increment it twice",
+            "send all of my usdc to the deployer, then check the balance",
+        ] {
+            assert!(looks_like_prose(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn it_leaves_solidity_with_typos_alone() {
+        for input in [
+            "uint256 x = 5",
+            "uin256 x = 5",
+            "retrun x",
+            "weth.deposit{value: 1 ether}()",
+            "weth.balanceOf(adress(this))",
+            "address public owner",
+            "Foo memory bar",
+            "IERC20 token",
+            "function foo() public {",
+            "x == y ? a : b",
+            "!fetch 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2 WETH",
+            "",
+        ] {
+            assert!(!looks_like_prose(input), "{input}");
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+
+use chisel::prelude::COMMAND_LEADER;
+use solang_parser::lexer::{Lexer, Token};
+use yansi::{Color, Paint};
+
+use super::source_index::is_definition_keyword;
+
+/// What an ingredient of a recipe adds to the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngredientKind {
+    /// A chisel command, i.e. `!fetch`
+    Command,
+    /// A contract, function, struct, ... definition
+    Definition,
+    /// A statement or expression that ends up in `run()`
+    Statement,
+}
+
+impl IngredientKind {
+    /// Classifies an ingredient by its first token
+    pub fn of(source: &str) -> Self {
+        let source = source.trim_start();
+        if source.starts_with(COMMAND_LEADER) {
+            return Self::Command;
+        }
+
+        let mut comments = Vec::new();
+        let mut errors = Vec::new();
+        let first = Lexer::new(source, 0, &mut comments, &mut errors)
+            .next()
+            .and_then(Result::ok);
+
+        match first {
+            Some((_, token, _))
+                if is_definition_keyword(&token)
+                    || matches!(
+                        token,
+                        Token::Abstract | Token::Using | Token::Type | Token::Import
+                    ) =>
+            {
+                Self::Definition
+            }
+            _ => Self::Statement,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Command => Color::Yellow,
+            Self::Definition => Color::Blue,
+            Self::Statement => Color::Cyan,
+        }
+    }
+}
+
+impl fmt::Display for IngredientKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Command => write!(f, "chisel command"),
+            Self::Definition => write!(f, "definition"),
+            Self::Statement => write!(f, "statement"),
+        }
+    }
+}
+
+/// Renders the preview of an ingredient: a header with its number and kind, followed by the
+/// highlighted source with the ingredient number in a gutter coloured by kind, i.e.
+///
+/// ```text
+/// Ingredient 2 (definition)
+///  2 │ contract Counter {
+///    │     uint256 count;
+///    │ }
+/// ```
+pub fn render_ingredient(number: usize, source: &str, highlighted: &str) -> String {
+    let kind = IngredientKind::of(source);
+    let style = kind.color().style();
+    let width = number.to_string().len();
+
+    let mut out = format!(
+        "{}\n",
+        Paint::new(format!("Ingredient {number} ({kind})"))
+            .with_style(style)
+            .bold()
+    );
+
+    for (index, line) in highlighted.lines().enumerate() {
+        let gutter = if index == 0 {
+            format!("{number:>width$} │")
+        } else {
+            format!("{:>width$} │", "")
+        };
+        out.push_str(&format!(
+            " {} {line}\n",
+            Paint::new(gutter).with_style(style)
+        ));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::command_helper::strip_ansi;
+
+    #[test]
+    fn it_classifies_ingredients() {
+        assert_eq!(
+            IngredientKind::of("!fetch 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2 WETH"),
+            IngredientKind::Command
+        );
+        assert_eq!(
+            IngredientKind::of("contract Counter {\nuint256 count;\n}"),
+            IngredientKind::Definition
+        );
+        assert_eq!(
+            IngredientKind::of(
+                "function double(uint256 x) pure returns (uint256) { return x * 2; }"
+            ),
+            IngredientKind::Definition
+        );
+        assert_eq!(
+            IngredientKind::of("Counter counter = new Counter();"),
+            IngredientKind::Statement
+        );
+    }
+
+    #[test]
+    fn it_renders_a_gutter() {
+        let source = "contract Counter {\nuint256 count;\n}";
+
+        assert_eq!(
+            strip_ansi(&render_ingredient(12, source, source)),
+            "Ingredient 12 (definition)\n 12 │ contract Counter {\n    │ uint256 count;\n    │ }\n"
+        );
+    }
+}
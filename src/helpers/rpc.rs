@@ -0,0 +1,288 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use chisel::prelude::COMMAND_LEADER;
+use eyre::{eyre, Result};
+use foundry_config::Config;
+use regex::Regex;
+use reqwest::Url;
+
+/// Names of chisel's fork command
+const FORK_COMMANDS: &[&str] = &["fork", "f"];
+
+/// Words that identify a network in RPC URLs and alias names. Layer 2s come first, since their
+/// URLs tend to mention `mainnet` as well, i.e. `arb-mainnet.g.alchemy.com`
+const NETWORKS: &[&[&str]] = &[
+    &["arbitrum", "arb", "arb1"],
+    &["optimism", "opt", "op"],
+    &["polygon", "matic"],
+    &["base"],
+    &["avalanche", "avax"],
+    &["bsc", "bnb"],
+    &["gnosis", "xdai"],
+    &["sepolia"],
+    &["goerli"],
+    &["mainnet", "eth", "ethereum"],
+];
+
+/// The `rpc_endpoints` of the project. Generated code forks through these aliases, so RPC URLs
+/// (and the API keys in them) never have to appear in a recipe.
+#[derive(Debug, Clone, Default)]
+pub struct RpcAliases {
+    /// URLs by alias, `None` if the URL references an environment variable that isn't set
+    endpoints: BTreeMap<String, Option<String>>,
+}
+
+impl FromIterator<(String, Option<String>)> for RpcAliases {
+    fn from_iter<T: IntoIterator<Item = (String, Option<String>)>>(iter: T) -> Self {
+        Self {
+            endpoints: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl RpcAliases {
+    pub fn from_config(config: &Config) -> Self {
+        config
+            .rpc_endpoints
+            .clone()
+            .resolved()
+            .iter()
+            .map(|(alias, url)| (alias.clone(), url.as_ref().ok().cloned()))
+            .collect()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.endpoints.keys().cloned().collect()
+    }
+
+    /// The alias `url` is configured under, forks created through an alias are created with the
+    /// URL it resolves to
+    pub fn alias_of(&self, url: &str) -> Option<&str> {
+        self.endpoints
+            .iter()
+            .find(|(_, configured)| configured.as_deref() == Some(url))
+            .map(|(alias, _)| alias.as_str())
+    }
+
+    /// The URL `alias` resolves to, `None` if there is no such alias or its URL can't be resolved
+    pub fn url_of(&self, alias: &str) -> Option<&str> {
+        self.endpoints.get(alias)?.as_deref()
+    }
+
+    /// Replaces RPC URLs in fork commands and `createSelectFork`/`createFork` calls with the
+    /// matching alias. Fails if a URL, or an alias the model made up, has no match.
+    pub fn rewrite(&self, ingredient: &str) -> Result<String> {
+        if let Some(command) = ingredient.trim().strip_prefix(COMMAND_LEADER) {
+            let mut args = command.split_whitespace();
+            return match (args.next(), args.next()) {
+                (Some(name), Some(target)) if FORK_COMMANDS.contains(&name) => {
+                    Ok(format!("{COMMAND_LEADER}{name} {}", self.resolve(target)?))
+                }
+                _ => Ok(ingredient.to_string()),
+            };
+        }
+
+        static FORK_RE: OnceLock<Regex> = OnceLock::new();
+        let fork_re = FORK_RE.get_or_init(|| {
+            Regex::new(r#"\b(createSelectFork|createFork)\s*\(\s*"([^"]*)""#).unwrap()
+        });
+
+        let mut out = String::with_capacity(ingredient.len());
+        let mut last = 0;
+        for captures in fork_re.captures_iter(ingredient) {
+            let target = captures.get(2).unwrap();
+            out.push_str(&ingredient[last..target.start()]);
+            out.push_str(self.resolve(target.as_str())?);
+            last = target.end();
+        }
+        out.push_str(&ingredient[last..]);
+
+        Ok(out)
+    }
+
+    /// The alias to fork with instead of `target`, a URL or alias
+    fn resolve<'a>(&'a self, target: &'a str) -> Result<&'a str> {
+        if self.endpoints.contains_key(target) {
+            return Ok(target);
+        }
+
+        let Ok(url) = Url::parse(target) else {
+            return Err(eyre!(
+                "No rpc_endpoints alias named `{target}`{}",
+                self.configured()
+            ));
+        };
+
+        // Local nodes are a deliberate choice rather than a made-up endpoint
+        if matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]")) {
+            return Ok(target);
+        }
+
+        self.alias_for(&url).ok_or_else(|| {
+            eyre!(
+                "No rpc_endpoints alias matches {}{}",
+                url.host_str().unwrap_or(target),
+                self.configured()
+            )
+        })
+    }
+
+    /// Finds the alias of a URL: one with the same URL or host, or failing that one for the same
+    /// network. Providers serve several networks from one host, i.e. `rpc.ankr.com/eth` and
+    /// `rpc.ankr.com/polygon`, so the host only counts if the networks don't tell them apart.
+    fn alias_for(&self, url: &Url) -> Option<&str> {
+        let wanted = url_network(url);
+        let same_url = |configured: &Url| {
+            configured.as_str().trim_end_matches('/') == url.as_str().trim_end_matches('/')
+        };
+        let same_host = |configured: &Url| {
+            let networks_agree = match (url_network(configured), wanted) {
+                (Some(configured), Some(wanted)) => configured == wanted,
+                _ => true,
+            };
+            configured.host_str() == url.host_str() && networks_agree
+        };
+
+        let parsed: Vec<(&str, Option<Url>)> = self
+            .endpoints
+            .iter()
+            .map(|(alias, configured)| {
+                (
+                    alias.as_str(),
+                    configured.as_deref().and_then(|url| Url::parse(url).ok()),
+                )
+            })
+            .collect();
+
+        let found = parsed
+            .iter()
+            .find(|(_, configured)| matches!(configured, Some(configured) if same_url(configured)))
+            .or_else(|| {
+                parsed
+                    .iter()
+                    .find(|(_, configured)| matches!(configured, Some(configured) if same_host(configured)))
+            });
+        if let Some((alias, _)) = found {
+            return Some(alias);
+        }
+
+        let wanted = wanted?;
+        parsed
+            .iter()
+            .find(|(alias, _)| network(alias) == Some(wanted))
+            .map(|(alias, _)| *alias)
+    }
+
+    fn configured(&self) -> String {
+        if self.endpoints.is_empty() {
+            ", the project has no rpc_endpoints configured in foundry.toml".to_string()
+        } else {
+            format!(", the configured aliases are: {}", self.names().join(", "))
+        }
+    }
+}
+
+/// The index into [NETWORKS] of the network the host and path of a URL refer to
+fn url_network(url: &Url) -> Option<usize> {
+    network(&format!("{}{}", url.host_str()?, url.path()))
+}
+
+/// The index into [NETWORKS] of the network a URL or alias refers to
+fn network(text: &str) -> Option<usize> {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text.split(|c: char| !c.is_ascii_alphanumeric()).collect();
+
+    NETWORKS
+        .iter()
+        .position(|names| names.iter().any(|name| words.contains(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases() -> RpcAliases {
+        RpcAliases::from_iter([
+            (
+                "mainnet".to_string(),
+                Some("https://eth-mainnet.g.alchemy.com/v2/secret".to_string()),
+            ),
+            ("arbitrum".to_string(), None),
+        ])
+    }
+
+    #[test]
+    fn it_rewrites_fork_urls_into_aliases() {
+        let aliases = aliases();
+
+        assert_eq!(
+            aliases
+                .rewrite("!f https://mainnet.infura.io/v3/84842078b09946638c03157f83405213")
+                .unwrap(),
+            "!f mainnet"
+        );
+        assert_eq!(
+            aliases
+                .rewrite("uint256 fork = vm.createSelectFork(\"https://arb-mainnet.g.alchemy.com/v2/key\", 1);")
+                .unwrap(),
+            "uint256 fork = vm.createSelectFork(\"arbitrum\", 1);"
+        );
+        assert_eq!(aliases.rewrite("!fork mainnet").unwrap(), "!fork mainnet");
+        assert_eq!(
+            aliases.rewrite("!f http://localhost:8545").unwrap(),
+            "!f http://localhost:8545"
+        );
+    }
+
+    #[test]
+    fn it_only_matches_hosts_serving_the_same_network() {
+        let aliases = RpcAliases::from_iter([
+            (
+                "mainnet".to_string(),
+                Some("https://rpc.ankr.com/eth".to_string()),
+            ),
+            (
+                "local".to_string(),
+                Some("https://node.example.com/rpc".to_string()),
+            ),
+        ]);
+
+        assert_eq!(
+            aliases
+                .rewrite("vm.createSelectFork(\"https://rpc.ankr.com/polygon\")")
+                .unwrap_err()
+                .to_string(),
+            "No rpc_endpoints alias matches rpc.ankr.com, the configured aliases are: local, mainnet"
+        );
+        assert_eq!(
+            aliases.rewrite("!f https://rpc.ankr.com/eth/key").unwrap(),
+            "!f mainnet"
+        );
+        assert_eq!(
+            aliases
+                .rewrite("!f https://node.example.com/other")
+                .unwrap(),
+            "!f local"
+        );
+    }
+
+    #[test]
+    fn it_fails_without_a_matching_alias() {
+        let aliases = aliases();
+
+        assert_eq!(
+            aliases
+                .rewrite("vm.createFork(\"https://polygon-rpc.com\")")
+                .unwrap_err()
+                .to_string(),
+            "No rpc_endpoints alias matches polygon-rpc.com, the configured aliases are: arbitrum, mainnet"
+        );
+        assert_eq!(
+            RpcAliases::default()
+                .rewrite("!f optimism")
+                .unwrap_err()
+                .to_string(),
+            "No rpc_endpoints alias named `optimism`, the project has no rpc_endpoints configured in foundry.toml"
+        );
+    }
+}
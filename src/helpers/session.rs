@@ -0,0 +1,34 @@
+use chisel::prelude::{ChiselCommand, ChiselDispatcher, DispatchResult};
+
+use super::command_helper::strip_ansi;
+
+/// Returns the source of the current session, without the formatting and highlighting applied by
+/// `ChiselCommand::Source`
+pub fn session_source(dispatcher: &ChiselDispatcher) -> Option<String> {
+    dispatcher
+        .session
+        .session_source
+        .as_ref()
+        .map(|source| source.to_repl_source())
+}
+
+/// The length of the session's run code, the body of `run()` that inputs are added to
+pub fn run_code_len(dispatcher: &ChiselDispatcher) -> Option<usize> {
+    dispatcher
+        .session
+        .session_source
+        .as_ref()
+        .map(|source| source.run_code.len())
+}
+
+/// Returns the session source formatted the way `ChiselCommand::Source` shows it, without the
+/// highlighting
+pub async fn formatted_session_source(dispatcher: &mut ChiselDispatcher) -> Option<String> {
+    match dispatcher
+        .dispatch_command(ChiselCommand::Source, &[])
+        .await
+    {
+        DispatchResult::CommandSuccess(Some(source)) => Some(strip_ansi(&source)),
+        _ => None,
+    }
+}
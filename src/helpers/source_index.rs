@@ -0,0 +1,197 @@
+use solang_parser::lexer::{Lexer, Token};
+
+type Spanned<'a> = (usize, Token<'a>, usize);
+
+/// Whether the token starts a named definition, i.e. `contract Name` or `function name`
+pub(crate) fn is_definition_keyword(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Contract
+            | Token::Interface
+            | Token::Library
+            | Token::Function
+            | Token::Struct
+            | Token::Enum
+            | Token::Event
+            | Token::Modifier
+    )
+}
+
+/// Whether the token can precede the name in a variable declaration, i.e. a type, visibility or
+/// data location
+fn is_declaration_prefix(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Uint(_)
+            | Token::Int(_)
+            | Token::Bytes(_)
+            | Token::Byte
+            | Token::DynamicBytes
+            | Token::Bool
+            | Token::Address
+            | Token::String
+            | Token::Payable
+            | Token::Identifier(_)
+            | Token::Public
+            | Token::Private
+            | Token::Internal
+            | Token::Constant
+            | Token::Immutable
+            | Token::Memory
+            | Token::Storage
+            | Token::Calldata
+            | Token::CloseBracket
+            | Token::CloseParenthesis
+    )
+}
+
+/// Names declared by the session, i.e. contracts, functions and variables, in the order they are
+/// declared. The names of the REPL contract itself are left out.
+pub fn declared_names(source: &str) -> Vec<String> {
+    let mut comments = Vec::new();
+    let mut errors = Vec::new();
+
+    let tokens: Vec<Spanned> = Lexer::new(source, 0, &mut comments, &mut errors)
+        .map_while(Result::ok)
+        .collect();
+
+    let mut names: Vec<String> = Vec::new();
+    for window in tokens.windows(3) {
+        let (previous, current, next) = (&window[0].1, &window[1].1, &window[2].1);
+
+        let Token::Identifier(name) = current else {
+            continue;
+        };
+
+        let declared = is_definition_keyword(previous)
+            || (is_declaration_prefix(previous)
+                && matches!(next, Token::Assign | Token::Semicolon));
+
+        if declared && !matches!(*name, "REPL" | "run" | "vm") && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    names
+}
+
+/// Finds the source of the contract, function, struct, ... named `name`. If there is no such
+/// definition the statements that mention a variable of that name are returned instead, so that
+/// both `Token` and `balance` can be looked up.
+pub fn find_definition(source: &str, name: &str) -> Option<String> {
+    let mut comments = Vec::new();
+    let mut errors = Vec::new();
+
+    // Stop at the first lexical error, everything before it is still useful
+    let tokens: Vec<Spanned> = Lexer::new(source, 0, &mut comments, &mut errors)
+        .map_while(Result::ok)
+        .collect();
+
+    let definition = tokens
+        .windows(2)
+        .position(|pair| is_definition_keyword(&pair[0].1) && pair[1].1 == Token::Identifier(name));
+
+    if let Some(start_index) = definition {
+        let start = tokens[start_index].0;
+        let mut depth = 0usize;
+
+        for (_, token, end) in &tokens[start_index + 2..] {
+            match token {
+                Token::OpenCurlyBrace => depth += 1,
+                Token::CloseCurlyBrace => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        return Some(source[start..*end].to_string());
+                    }
+                }
+                // Declarations without a body, i.e. interface functions and events
+                Token::Semicolon if depth == 0 => return Some(source[start..*end].to_string()),
+                _ => {}
+            }
+        }
+
+        return Some(source[start..].to_string());
+    }
+
+    let mut statements: Vec<String> = Vec::new();
+    let mut statement_start = 0;
+    let mut mentioned = false;
+
+    for (_, token, end) in &tokens {
+        match token {
+            Token::Semicolon | Token::OpenCurlyBrace | Token::CloseCurlyBrace => {
+                if mentioned && *token == Token::Semicolon {
+                    let statement = source[statement_start..*end].trim().to_string();
+                    if !statements.contains(&statement) {
+                        statements.push(statement);
+                    }
+                }
+                mentioned = false;
+                statement_start = *end;
+            }
+            Token::Identifier(identifier) if *identifier == name => mentioned = true,
+            _ => {}
+        }
+    }
+
+    (!statements.is_empty()).then(|| statements.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+interface IERC20 {
+    function balanceOf(address owner) external view returns (uint256);
+}
+
+contract Token {
+    uint256 public supply;
+
+    function mint(uint256 amount) public {
+        if (amount > 0) {
+            supply += amount;
+        }
+    }
+}
+
+contract REPL {
+    function run() public {
+        uint256 total = 10;
+        total += 5;
+    }
+}
+";
+
+    #[test]
+    fn it_finds_contracts_and_functions() {
+        assert!(find_definition(SOURCE, "Token")
+            .unwrap()
+            .starts_with("contract Token {"));
+        assert!(find_definition(SOURCE, "Token")
+            .unwrap()
+            .ends_with("}\n    }\n}"));
+        assert_eq!(
+            find_definition(SOURCE, "balanceOf").unwrap(),
+            "function balanceOf(address owner) external view returns (uint256);"
+        );
+    }
+
+    #[test]
+    fn it_lists_declared_names() {
+        assert_eq!(
+            declared_names(SOURCE),
+            vec!["IERC20", "balanceOf", "Token", "supply", "mint", "total"]
+        );
+    }
+
+    #[test]
+    fn it_finds_variable_statements() {
+        assert_eq!(
+            find_definition(SOURCE, "total").unwrap(),
+            "uint256 total = 10;\ntotal += 5;"
+        );
+        assert!(find_definition(SOURCE, "missing").is_none());
+    }
+}
@@ -1,13 +1,17 @@
 //! Chisel CLI start-up script, modified to introduce the !chat command
 
+mod commands;
 mod completion;
+mod export;
 mod helpers;
 
 use chisel::{
     history::chisel_history_file,
     prelude::{ChiselCommand, ChiselDispatcher, DispatchResult},
+    solidity_helper::SolidityHelper,
 };
 use clap::Parser;
+use eyre::WrapErr;
 use foundry_cli::cmd::{forge::build::BuildArgs, LoadConfig};
 use foundry_common::evm::EvmArgs;
 use foundry_config::{
@@ -18,11 +22,37 @@ use foundry_config::{
     Config,
 };
 use rustyline::{config::Configurer, error::ReadlineError, Editor};
+use std::{io::IsTerminal, path::PathBuf, str::FromStr};
 use yansi::Paint;
 
 use crate::{
-    completion::complete::CompletionClient,
-    helpers::{command_helper::CommandHelper, dispatch::log_dispatch_result},
+    commands::{help_section, ChiselGptCommand},
+    completion::{
+        cache::ResponseCache,
+        complete::CompletionClient,
+        executions::trace_source,
+        profile::ChiselGptConfig,
+        transcript::{IngredientOutcome, Transcript},
+    },
+    export::{
+        script::{export_script, ScriptExport},
+        test::export_test,
+        Inspections,
+    },
+    helpers::{
+        address_book::AddressBook,
+        cheatcodes::{load_cheatcodes, parse_signatures},
+        command_helper::CommandHelper,
+        confirm::confirm,
+        diff::render_diff,
+        dispatch::log_dispatch_result,
+        external_contracts::fetched_contract,
+        prompt_file::{parse_prompt_file, EntryReader},
+        prose::looks_like_prose,
+        rpc::RpcAliases,
+        session::session_source,
+        source_index::declared_names,
+    },
 };
 
 // Loads project's figment and merges the build cli arguments into it
@@ -41,6 +71,14 @@ pub struct ChiselParser {
     #[clap(flatten)]
     pub evm_opts: EvmArgs,
 
+    /// Offer to send input that reads like a request, rather than Solidity, to `!chat`
+    #[clap(long)]
+    pub auto: bool,
+
+    /// Always query the model instead of reusing cached chat responses
+    #[clap(long)]
+    pub no_chat_cache: bool,
+
     #[command(subcommand)]
     pub sub: Option<ChiselParserSub>,
 }
@@ -56,6 +94,28 @@ pub enum ChiselParserSub {
     View { id: String },
     /// Clear all cached chisel sessions from the cache directory
     ClearCache,
+    /// Export a cached session
+    #[clap(group(clap::ArgGroup::new("kind").required(true).args(["test", "script"])))]
+    Export {
+        id: String,
+        /// Export the session as a forge test
+        #[clap(long)]
+        test: bool,
+        /// Export the session as a forge script
+        #[clap(long)]
+        script: bool,
+        /// Path of the exported file
+        #[clap(long, short)]
+        out: Option<String>,
+    },
+    /// Run the `!chat` requests and chisel inputs of a file in a new session, then save it
+    RunPrompts {
+        /// The prompt file, with an entry per line or per block of Solidity
+        file: PathBuf,
+        /// Carry on with the next entry when one fails
+        #[clap(long)]
+        keep_going: bool,
+    },
 }
 
 #[tokio::main]
@@ -68,6 +128,12 @@ async fn main() -> eyre::Result<()> {
     // Parse command args
     let args = ChiselParser::parse();
 
+    // Without a terminal there is nobody to prompt, and colours would end up in files and pipes
+    let piped = args.sub.is_none() && !std::io::stdin().is_terminal();
+    if piped {
+        Paint::disable();
+    }
+
     // Keeps track of whether or not an interrupt was the last input
     let mut interrupt = false;
 
@@ -83,7 +149,17 @@ async fn main() -> eyre::Result<()> {
         backend: None,
     })?;
 
-    let completion = CompletionClient::new(&mut dispatcher, config.fmt).await;
+    let gpt_config = ChiselGptConfig::load(&config.__root.0)?;
+    let address_book = AddressBook::load(&gpt_config.address_book)?;
+    let mut completion = CompletionClient::new(
+        &mut dispatcher,
+        config.fmt.clone(),
+        !args.no_chat_cache,
+        gpt_config,
+        RpcAliases::from_config(&config),
+        address_book,
+    )
+    .await;
 
     // Check for chisel subcommands
     match &args.sub {
@@ -100,8 +176,10 @@ async fn main() -> eyre::Result<()> {
             }
             return Ok(());
         }
-        Some(ChiselParserSub::Load { id }) | Some(ChiselParserSub::View { id }) => {
-            // For both of these subcommands, we need to attempt to load the session from cache
+        Some(ChiselParserSub::Load { id })
+        | Some(ChiselParserSub::View { id })
+        | Some(ChiselParserSub::Export { id, .. }) => {
+            // For all of these subcommands, we need to attempt to load the session from cache
             match dispatcher
                 .dispatch_command(ChiselCommand::Load, &[id])
                 .await
@@ -114,8 +192,40 @@ async fn main() -> eyre::Result<()> {
                 _ => panic!("Unexpected result! Please report this bug."),
             }
 
+            // Restore the chat history of the session along with its source
+            completion.load_transcript(id);
+
+            if let Some(ChiselParserSub::Export { out, test, .. }) = &args.sub {
+                if *test {
+                    let inspections =
+                        Inspections::from_transcript(completion.transcript(), &dispatcher);
+                    log_test_export(export_test(
+                        &dispatcher,
+                        &inspections,
+                        out.as_deref(),
+                        config.fmt.clone(),
+                    ));
+                } else {
+                    log_script_export(export_script(
+                        &dispatcher,
+                        out.as_deref(),
+                        config.fmt.clone(),
+                    ));
+                }
+                return Ok(());
+            }
+
             // If the subcommand was `view`, print the source and exit.
             if matches!(args.sub, Some(ChiselParserSub::View { .. })) {
+                // Interleave the chat requests with the source they produced
+                if !completion.transcript().entries.is_empty() {
+                    if let Some(source) = session_source(&dispatcher) {
+                        let annotated = completion.transcript().interleave(&source);
+                        println!("{}", SolidityHelper::highlight(&annotated));
+                        return Ok(());
+                    }
+                }
+
                 match dispatcher
                     .dispatch_command(ChiselCommand::Source, &[])
                     .await
@@ -137,14 +247,51 @@ async fn main() -> eyre::Result<()> {
                 DispatchResult::CommandFailed(e) => eprintln!("{e}"),
                 _ => panic!("Unexpected result! Please report this bug."),
             }
+            clear_chat_cache();
+            return Ok(());
+        }
+        Some(ChiselParserSub::RunPrompts { file, keep_going }) => {
+            let contents = std::fs::read_to_string(file)
+                .wrap_err_with(|| format!("Failed to read {}", file.display()))?;
+            let entries = parse_prompt_file(&contents)?;
+
+            let failures =
+                run_prompts(&mut completion, &mut dispatcher, &entries, *keep_going).await;
+
+            // The session is saved even if an entry failed, so that the failure can be inspected
+            let saved = dispatcher.dispatch_command(ChiselCommand::Save, &[]).await;
+            log_dispatch_result(&saved);
+            completion.save_transcript(&dispatcher);
+
+            if failures > 0 {
+                return Err(eyre::eyre!(
+                    "{failures} of {} entries in {} failed",
+                    entries.len(),
+                    file.display()
+                ));
+            }
             return Ok(());
         }
         None => { /* No chisel subcommand present; Continue */ }
     }
 
+    // Piped input is run line by line instead of starting the REPL
+    if piped {
+        let failures = run_stdin(&mut completion, &mut dispatcher).await?;
+        if failures > 0 {
+            return Err(eyre::eyre!("{failures} inputs failed"));
+        }
+        return Ok(());
+    }
+
     // Create a new rustyline Editor
     let mut rl = Editor::<CommandHelper, _>::new()?;
-    rl.set_helper(Some(CommandHelper::new()));
+    let mut helper = CommandHelper::new();
+    helper
+        .set_commands(CommandHelper::parse_commands(completion.help_text()))
+        .set_cheatcodes(load_cheatcodes(&config.__root.0))
+        .set_auto(args.auto);
+    rl.set_helper(Some(helper));
 
     // automatically add lines to history
     rl.set_auto_add_history(true);
@@ -154,6 +301,12 @@ async fn main() -> eyre::Result<()> {
         let _ = rl.load_history(&chisel_history);
     }
 
+    // Inspected values, used to derive assertions when exporting the session as a test
+    let mut inspections = Inspections::from_transcript(completion.transcript(), &dispatcher);
+
+    // The last input, if it failed, used to explain and fix the failure
+    let mut last_failure: Option<FailedInput> = None;
+
     // Print welcome header
     println!(
         "Welcome to Chisel! Type `{}` to show available commands.",
@@ -164,11 +317,18 @@ async fn main() -> eyre::Result<()> {
     loop {
         // Get the prompt from the dispatcher
         // Variable based on status of the last entry
-        let prompt = dispatcher.get_prompt();
-        rl.helper_mut().unwrap().set_errored(dispatcher.errored);
+        let source = session_source(&dispatcher).unwrap_or_default();
+        let helper = rl.helper_mut().unwrap();
+        helper
+            .set_errored(dispatcher.errored)
+            .set_profile(completion.profile_name())
+            .set_session_names(declared_names(&source))
+            .set_session_functions(parse_signatures(&source));
+
+        let prompt = helper.prompt(&dispatcher.get_prompt());
 
         // Read the next line
-        let next_string = rl.readline(prompt.as_ref());
+        let next_string = rl.readline(&prompt);
 
         // Try to read the string
         match next_string {
@@ -176,14 +336,210 @@ async fn main() -> eyre::Result<()> {
                 // Clear interrupt flag
                 interrupt = false;
 
-                if line.starts_with("!chat") {
-                    completion
-                        .handle_chat_request(&mut dispatcher, line)
-                        .await
-                        .unwrap();
+                // In auto mode requests can be typed without `!chat`, as long as they're confirmed
+                let line = if args.auto
+                    && looks_like_prose(&line)
+                    && confirm("This doesn't look like Solidity, send it to !chat?")
+                {
+                    format!("!chat {line}")
                 } else {
-                    let dispatch_result = dispatcher.dispatch(&line).await;
-                    log_dispatch_result(&dispatch_result);
+                    line
+                };
+
+                match ChiselGptCommand::parse(&line) {
+                    Some((ChiselGptCommand::Chat, _)) => {
+                        if let Err(e) = completion.handle_chat_request(&mut dispatcher, line).await
+                        {
+                            eprintln!("{}", Paint::red(e));
+                            continue;
+                        }
+
+                        if let Some(entry) = completion.transcript().entries.last() {
+                            inspections.record_entry(&dispatcher, entry);
+
+                            for ingredient in &entry.ingredients {
+                                if let (Some((address, _)), IngredientOutcome::Success(_)) =
+                                    (fetched_contract(&ingredient.source), &ingredient.outcome)
+                                {
+                                    rl.helper_mut().unwrap().add_fetched_address(address);
+                                }
+                            }
+
+                            last_failure = entry.ingredients.iter().find_map(|ingredient| {
+                                match &ingredient.outcome {
+                                    IngredientOutcome::Failure(e) => Some(FailedInput {
+                                        input: ingredient.source.clone(),
+                                        result: DispatchResult::CommandFailed(e.clone()),
+                                    }),
+                                    IngredientOutcome::Success(_)
+                                    | IngredientOutcome::NotDispatched => None,
+                                }
+                            });
+                        }
+                    }
+                    Some((ChiselGptCommand::Explain, args)) => {
+                        let args: Vec<&str> = args.split_whitespace().collect();
+                        let include_last = args.contains(&"--last");
+                        let target = args.into_iter().find(|arg| *arg != "--last");
+
+                        let last_error = last_failure
+                            .as_ref()
+                            .filter(|_| include_last)
+                            .map(FailedInput::error);
+
+                        if let Err(e) = completion
+                            .handle_explain_request(&dispatcher, target, last_error.as_deref())
+                            .await
+                        {
+                            eprintln!("{}", Paint::red(e));
+                        }
+                    }
+                    Some((ChiselGptCommand::Fix, _)) => match last_failure.take() {
+                        Some(failure) => {
+                            last_failure = fix_failed_input(
+                                &completion,
+                                &mut dispatcher,
+                                &mut inspections,
+                                failure,
+                            )
+                            .await;
+                        }
+                        None => eprintln!(
+                            "{}",
+                            Paint::red("Nothing to fix, the last input didn't fail")
+                        ),
+                    },
+                    Some((ChiselGptCommand::ExportTest, args)) => {
+                        let path = args.split_whitespace().next();
+                        log_test_export(export_test(
+                            &dispatcher,
+                            &inspections,
+                            path,
+                            config.fmt.clone(),
+                        ));
+                    }
+                    Some((ChiselGptCommand::ExportScript, args)) => {
+                        let path = args.split_whitespace().next();
+                        log_script_export(export_script(&dispatcher, path, config.fmt.clone()));
+                    }
+                    Some((ChiselGptCommand::Model, "")) => {
+                        for (name, profile) in completion.profiles() {
+                            let marker = if name == completion.profile_name() {
+                                "*"
+                            } else {
+                                " "
+                            };
+                            println!(
+                                "{marker} {} ({}, {:?})",
+                                Paint::magenta(name),
+                                profile.model,
+                                profile.backend
+                            );
+                        }
+                    }
+                    Some((ChiselGptCommand::Model, name)) => match completion.set_profile(name) {
+                        Ok(profile) => println!(
+                            "{}",
+                            Paint::green(format!("Switched to `{name}` ({})", profile.model))
+                        ),
+                        Err(e) => eprintln!("{}", Paint::red(e)),
+                    },
+                    Some((ChiselGptCommand::Label, "")) => {
+                        let chain_id = completion.chain_id(&dispatcher).await;
+                        for (name, address) in completion.address_book().entries(chain_id) {
+                            println!("{} {address}", Paint::magenta(format!("@{name}")));
+                        }
+                    }
+                    Some((ChiselGptCommand::Label, args)) => {
+                        match args.split_whitespace().collect::<Vec<_>>()[..] {
+                            [name, address] => {
+                                match completion.address_book_mut().label(name, address) {
+                                    Ok(()) => println!(
+                                        "{}",
+                                        Paint::green(format!("Labelled {address} as @{name}"))
+                                    ),
+                                    Err(e) => eprintln!("{}", Paint::red(e)),
+                                }
+                            }
+                            _ => eprintln!("{}", Paint::red("Usage: !label <name> <address>")),
+                        }
+                    }
+                    Some((ChiselGptCommand::Checkpoint, name)) => {
+                        match completion.create_checkpoint(&dispatcher, name).await {
+                            Ok(()) => {
+                                println!("{}", Paint::green(format!("Created checkpoint `{name}`")))
+                            }
+                            Err(e) => eprintln!("{}", Paint::red(e)),
+                        }
+                    }
+                    Some((ChiselGptCommand::Checkpoints, _)) => {
+                        for checkpoint in completion.checkpoints().iter() {
+                            match &checkpoint.request {
+                                Some(request) => println!(
+                                    "{} before {request}",
+                                    Paint::magenta(&checkpoint.name)
+                                ),
+                                None => println!("{}", Paint::magenta(&checkpoint.name)),
+                            }
+                        }
+                    }
+                    Some((ChiselGptCommand::Restore, name)) => {
+                        match completion.restore_checkpoint(&mut dispatcher, name) {
+                            Ok(()) => {
+                                println!(
+                                    "{}",
+                                    Paint::green(format!(
+                                        "Restored checkpoint `{name}`, the session is re-executed from there on the next input"
+                                    ))
+                                );
+                                inspections = Inspections::from_transcript(
+                                    completion.transcript(),
+                                    &dispatcher,
+                                );
+                                last_failure = None;
+                            }
+                            Err(e) => eprintln!("{}", Paint::red(e)),
+                        }
+                    }
+                    Some((ChiselGptCommand::Diff, n)) => {
+                        let n = if n.is_empty() {
+                            Ok(1)
+                        } else {
+                            n.parse::<usize>()
+                        };
+                        match n.ok().and_then(|n| completion.source_change(n)) {
+                            Some(change) => {
+                                println!(
+                                    "{}",
+                                    Paint::cyan(format!("Changes made by {}", change.request))
+                                );
+                                match change.render() {
+                                    Some(diff) => print!("{diff}"),
+                                    None => println!("The session source didn't change"),
+                                }
+                            }
+                            None => eprintln!(
+                                "{}",
+                                Paint::red(
+                                    "No such recipe, `!diff 1` shows the changes of the last one"
+                                )
+                            ),
+                        }
+                    }
+                    None => {
+                        let (dispatch_result, fetched) = dispatch_input(
+                            &mut completion,
+                            &mut dispatcher,
+                            &mut inspections,
+                            &line,
+                        )
+                        .await;
+                        if let Some(address) = fetched {
+                            rl.helper_mut().unwrap().add_fetched_address(&address);
+                        }
+
+                        last_failure = FailedInput::new(line, dispatch_result);
+                    }
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -209,6 +565,306 @@ async fn main() -> eyre::Result<()> {
     Ok(())
 }
 
+/// Dispatches a chisel input, from the REPL, a prompt file or piped input, and keeps the chat
+/// state in step with the session: what came of the input, the contract it fetched, and a fresh
+/// start once the session is cleared or another one is loaded. Returns the result along with the
+/// address of the contract the input fetched, if any.
+async fn dispatch_input(
+    completion: &mut CompletionClient,
+    dispatcher: &mut ChiselDispatcher,
+    inspections: &mut Inspections,
+    input: &str,
+) -> (DispatchResult, Option<String>) {
+    let before = trace_source(dispatcher);
+    let dispatch_result = dispatcher.dispatch(input).await;
+    log_dispatch_result(&dispatch_result);
+    completion
+        .record_execution(before.as_ref(), input, &dispatch_result)
+        .await;
+
+    if let DispatchResult::Success(output) = &dispatch_result {
+        inspections.record(dispatcher, input, output.as_deref());
+    }
+
+    let mut fetched = None;
+    if matches!(dispatch_result, DispatchResult::CommandSuccess(_)) {
+        match parse_chisel_command(input) {
+            Some(ChiselCommand::Help) => println!("{}", help_section()),
+            Some(ChiselCommand::ClearCache) => clear_chat_cache(),
+            Some(ChiselCommand::Save) => completion.save_transcript(dispatcher),
+            Some(ChiselCommand::Load) => {
+                if let Some(id) = dispatcher.session.id.clone() {
+                    completion.load_transcript(&id);
+                }
+                completion.forget_session();
+                *inspections = Inspections::from_transcript(completion.transcript(), dispatcher);
+            }
+            Some(ChiselCommand::Clear) => {
+                completion.clear_transcript();
+                completion.forget_session();
+                *inspections = Inspections::default();
+            }
+            Some(ChiselCommand::Fetch) => {
+                fetched = completion
+                    .external_contracts_mut()
+                    .record(input)
+                    .map(|contract| contract.address.clone());
+            }
+            _ => {}
+        }
+    }
+
+    (dispatch_result, fetched)
+}
+
+/// Parses the chisel command at the start of the line, if there is one
+fn parse_chisel_command(line: &str) -> Option<ChiselCommand> {
+    line.trim()
+        .strip_prefix('!')
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|cmd| ChiselCommand::from_str(cmd).ok())
+}
+
+/// Clears the cached chat responses, and the transcripts of chisel's cached sessions, along with
+/// the sessions
+fn clear_chat_cache() {
+    if let Err(e) = ResponseCache::clear() {
+        eprintln!(
+            "{}",
+            Paint::red(format!("Failed to clear cached chat responses: {e}"))
+        );
+    }
+    if let Err(e) = Transcript::clear_all() {
+        eprintln!(
+            "{}",
+            Paint::red(format!("Failed to clear chat transcripts: {e}"))
+        );
+    }
+}
+
+/// An input that failed to dispatch, along with the result it failed with
+struct FailedInput {
+    input: String,
+    result: DispatchResult,
+}
+
+impl FailedInput {
+    /// Returns `None` if the result isn't a failure
+    fn new(input: String, result: DispatchResult) -> Option<Self> {
+        match IngredientOutcome::from(&result) {
+            IngredientOutcome::Failure(_) => Some(Self { input, result }),
+            _ => None,
+        }
+    }
+
+    /// The compiler diagnostic, revert reason or error message of the failure
+    fn error(&self) -> String {
+        match IngredientOutcome::from(&self.result) {
+            IngredientOutcome::Failure(e) => e,
+            _ => String::new(),
+        }
+    }
+}
+
+/// Asks the model for a fix to the failed input, shows it as a diff against the original and
+/// dispatches it once confirmed. Returns the input that is still failing, if any.
+async fn fix_failed_input(
+    completion: &CompletionClient,
+    dispatcher: &mut ChiselDispatcher,
+    inspections: &mut Inspections,
+    failure: FailedInput,
+) -> Option<FailedInput> {
+    let fix = match completion
+        .handle_fix_request(dispatcher, &failure.input, &failure.error())
+        .await
+    {
+        Ok(fix) if !fix.is_empty() => fix,
+        Ok(_) => {
+            eprintln!("{}", Paint::red("No fix was proposed"));
+            return Some(failure);
+        }
+        Err(e) => {
+            eprintln!("{}", Paint::red(e));
+            return Some(failure);
+        }
+    };
+
+    println!(
+        "{}",
+        render_diff(&failure.input, &fix.join("\n"), "input", "fix")
+    );
+
+    if completion.policy().enforce(&fix).is_some() || !confirm("Dispatch the fix?") {
+        return Some(failure);
+    }
+
+    for command in fix {
+        let dispatch_result = dispatcher.dispatch(&command).await;
+        log_dispatch_result(&dispatch_result);
+
+        if let DispatchResult::Success(output) = &dispatch_result {
+            inspections.record(dispatcher, &command, output.as_deref());
+        }
+
+        if let Some(failure) = FailedInput::new(command, dispatch_result) {
+            return Some(failure);
+        }
+    }
+
+    None
+}
+
+/// Runs the entries of a prompt file one after the other, like they were typed into the REPL.
+/// Returns how many failed, an entry that fails stops the run unless `keep_going` is set.
+async fn run_prompts(
+    completion: &mut CompletionClient,
+    dispatcher: &mut ChiselDispatcher,
+    entries: &[String],
+    keep_going: bool,
+) -> usize {
+    let mut failures = 0;
+    let mut inspections = Inspections::default();
+
+    for (index, entry) in entries.iter().enumerate() {
+        println!(
+            "{}",
+            Paint::cyan(format!("[{}/{}] {entry}", index + 1, entries.len()))
+        );
+
+        if !run_entry(completion, dispatcher, &mut inspections, entry).await {
+            failures += 1;
+            if !keep_going {
+                eprintln!(
+                    "{}",
+                    Paint::red(format!(
+                        "Stopped at entry {}, pass --keep-going to run the remaining entries",
+                        index + 1
+                    ))
+                );
+                break;
+            }
+        }
+    }
+
+    failures
+}
+
+/// Reads inputs from stdin when it isn't a terminal, i.e. `echo '!chat deal me 1 ether' |
+/// chisel_gpt`. There is no prompt, and inputs are grouped into entries by an [EntryReader]. Every
+/// entry is run, returns how many failed.
+async fn run_stdin(
+    completion: &mut CompletionClient,
+    dispatcher: &mut ChiselDispatcher,
+) -> eyre::Result<usize> {
+    let mut failures = 0;
+    let mut reader = EntryReader::default();
+    let mut inspections = Inspections::default();
+
+    for line in std::io::stdin().lines() {
+        for entry in reader.push(&line?) {
+            if !run_entry(completion, dispatcher, &mut inspections, &entry).await {
+                failures += 1;
+            }
+        }
+    }
+
+    let last = reader.finish().unwrap_or_else(|input| {
+        eprintln!("Input ended before the last block was closed:\n{input}");
+        failures += 1;
+        None
+    });
+    if let Some(entry) = last {
+        if !run_entry(completion, dispatcher, &mut inspections, &entry).await {
+            failures += 1;
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Runs a single `!chat` request or chisel input outside of the REPL, returns whether it
+/// succeeded
+async fn run_entry(
+    completion: &mut CompletionClient,
+    dispatcher: &mut ChiselDispatcher,
+    inspections: &mut Inspections,
+    entry: &str,
+) -> bool {
+    match ChiselGptCommand::parse(entry) {
+        Some((ChiselGptCommand::Chat, _)) => {
+            match completion
+                .handle_chat_request(dispatcher, entry.to_string())
+                .await
+            {
+                // A request that produced no recipe, or one that was held back, failed too
+                Ok(()) => match completion.transcript().entries.last() {
+                    Some(entry) => {
+                        !entry.ingredients.is_empty()
+                            && entry.ingredients.iter().all(|ingredient| {
+                                matches!(ingredient.outcome, IngredientOutcome::Success(_))
+                            })
+                    }
+                    None => false,
+                },
+                Err(e) => {
+                    eprintln!("{}", Paint::red(e));
+                    false
+                }
+            }
+        }
+        Some(_) => {
+            let name = entry.split_whitespace().next().unwrap_or_default();
+            eprintln!(
+                "{}",
+                Paint::red(format!(
+                    "`{name}` only works in the REPL, other inputs can only be !chat requests and chisel inputs"
+                ))
+            );
+            false
+        }
+        None => {
+            let (dispatch_result, _) =
+                dispatch_input(completion, dispatcher, inspections, entry).await;
+
+            matches!(
+                IngredientOutcome::from(&dispatch_result),
+                IngredientOutcome::Success(_)
+            )
+        }
+    }
+}
+
+fn log_test_export(result: eyre::Result<PathBuf>) {
+    match result {
+        Ok(path) => println!(
+            "{}",
+            Paint::green(format!("Exported session to {}", path.display()))
+        ),
+        Err(e) => eprintln!("{}", Paint::red(e)),
+    }
+}
+
+fn log_script_export(result: eyre::Result<ScriptExport>) {
+    match result {
+        Ok(export) => {
+            println!(
+                "{}",
+                Paint::green(format!("Exported session to {}", export.path.display()))
+            );
+            if !export.env_vars.is_empty() {
+                println!(
+                    "{}",
+                    Paint::yellow(format!(
+                        "Secrets were replaced with environment variables, set {} before running the script",
+                        export.env_vars.join(", ")
+                    ))
+                );
+            }
+        }
+        Err(e) => eprintln!("{}", Paint::red(e)),
+    }
+}
+
 /// [Provider] impl
 impl Provider for ChiselParser {
     fn metadata(&self) -> Metadata {